@@ -0,0 +1,196 @@
+use crate::maths::{Matrix4,point};
+use std::io;
+use crate::canvas::{Canvas,RowWriter};
+use crate::ray::Ray;
+use crate::world::World;
+
+/// A pinhole camera one unit in front of a canvas of `hsize` by
+/// `vsize` pixels. `field_of_view` is in radians.
+#[derive(Debug,Clone,Copy)]
+pub struct Camera {
+    hsize: usize,
+    vsize: usize,
+    field_of_view: f64,
+    transform: Matrix4,
+    inverse: Matrix4,
+    half_width: f64,
+    half_height: f64,
+    pixel_size: f64,
+}
+
+impl Camera {
+    pub fn new(hsize: usize, vsize: usize, field_of_view: f64) -> Self {
+        let half_view = f64::tan(field_of_view / 2.0);
+        let aspect = hsize as f64 / vsize as f64;
+        let (half_width, half_height) = if aspect >= 1.0 {
+            (half_view, half_view / aspect)
+        } else {
+            (half_view * aspect, half_view)
+        };
+        Self {
+            hsize, vsize, field_of_view,
+            transform: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+            half_width, half_height,
+            pixel_size: half_width * 2.0 / hsize as f64,
+        }
+    }
+
+    pub fn hsize(&self) -> usize {
+        self.hsize
+    }
+
+    pub fn vsize(&self) -> usize {
+        self.vsize
+    }
+
+    pub fn field_of_view(&self) -> f64 {
+        self.field_of_view
+    }
+
+    pub fn transform(&self) -> Matrix4 {
+        self.transform
+    }
+
+    /// Panics if `m` is singular.
+    pub fn set_transform(&mut self, m: Matrix4) {
+        self.inverse = m.inverse()
+            .expect("Singular transform in Camera::set_transform()");
+        self.transform = m;
+    }
+
+    pub fn with_transform(mut self, m: Matrix4) -> Self {
+        self.set_transform(m);
+        self
+    }
+
+    pub fn pixel_size(&self) -> f64 {
+        self.pixel_size
+    }
+
+    /// Ray from the camera through the centre of pixel `(px, py)`.
+    pub fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
+        let xoffset = (px as f64 + 0.5) * self.pixel_size;
+        let yoffset = (py as f64 + 0.5) * self.pixel_size;
+
+        // the camera looks toward -z, so +x is to the left
+        let world_x = self.half_width - xoffset;
+        let world_y = self.half_height - yoffset;
+
+        let pixel = self.inverse * point(world_x, world_y, -1.0);
+        let origin = self.inverse * point(0.0, 0.0, 0.0);
+        Ray::new(origin, (pixel - origin).normalised())
+    }
+
+    pub fn render(&self, world: &World) -> Canvas {
+        let mut image = Canvas::new(self.hsize, self.vsize);
+        for y in 0..self.vsize {
+            for x in 0..self.hsize {
+                let ray = self.ray_for_pixel(x, y);
+                image.write_rgba(x, y, world.rgba_at(&ray));
+            }
+        }
+        image
+    }
+
+    /// Renders row by row into `writer`, which must be the camera's
+    /// size, and finishes it; only one row is held at a time.
+    pub fn render_to(&self, world: &World, writer: &mut impl RowWriter) -> io::Result<()> {
+        let mut row = Vec::with_capacity(self.hsize);
+        for y in 0..self.vsize {
+            row.clear();
+            row.extend((0..self.hsize).map(|x| world.rgba_at(&self.ray_for_pixel(x, y))));
+            writer.write_rgba_row(&row)?;
+        }
+        writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::PI;
+    use crate::maths::{vector,almost_same};
+    use crate::canvas::{Colour,PngStream,assert_golden};
+    use crate::world::test::default_world;
+
+    #[test]
+    fn pixel_size() {
+        assert!(almost_same(Camera::new(200, 125, PI/2.0).pixel_size(), 0.01));
+        assert!(almost_same(Camera::new(125, 200, PI/2.0).pixel_size(), 0.01));
+    }
+
+    #[test]
+    fn accessors() {
+        let c = Camera::new(160, 120, PI/2.0);
+        assert_eq!(c.hsize(), 160);
+        assert_eq!(c.vsize(), 120);
+        assert_eq!(c.field_of_view(), PI/2.0);
+        assert_eq!(c.transform(), Matrix4::identity());
+    }
+
+    #[test]
+    fn ray_for_pixel() {
+        let c = Camera::new(201, 101, PI/2.0);
+        let r = c.ray_for_pixel(100, 50);
+        assert_eq!(r.origin, point(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, vector(0.0, 0.0, -1.0));
+
+        let r = c.ray_for_pixel(0, 0);
+        assert_eq!(r.origin, point(0.0, 0.0, 0.0));
+        assert!(f64::abs(r.direction.x - 0.66519) < 1.0e-5);
+        assert!(f64::abs(r.direction.y - 0.33259) < 1.0e-5);
+        assert!(f64::abs(r.direction.z + 0.66851) < 1.0e-5);
+
+        let c = c.with_transform(Matrix4::identity()
+            .translate(0.0, -2.0, 5.0)
+            .rotate_y(PI/4.0));
+        let r = c.ray_for_pixel(100, 50);
+        let h = f64::sqrt(2.0)/2.0;
+        assert_eq!(r.origin, point(0.0, 2.0, -5.0));
+        assert_eq!(r.direction, vector(h, 0.0, -h));
+    }
+
+    #[test]
+    fn render() {
+        let w = default_world();
+        let from = point(0.0, 0.0, -5.0);
+        let to = point(0.0, 0.0, 0.0);
+        let up = vector(0.0, 1.0, 0.0);
+        let c = Camera::new(11, 11, PI/2.0)
+            .with_transform(Matrix4::view_transform(from, to, up));
+        let image = c.render(&w);
+        let col = image.pixel_at(5, 5);
+        let expected = Colour::new(0.38066, 0.47583, 0.2855);
+        assert!(f64::abs(col.red - expected.red) < 1.0e-5);
+        assert!(f64::abs(col.green - expected.green) < 1.0e-5);
+        assert!(f64::abs(col.blue - expected.blue) < 1.0e-5);
+        assert_eq!(image.alpha_at(5, 5), 1.0);
+        assert_eq!(image.alpha_at(0, 0), 0.0);
+    }
+
+    #[test]
+    fn render_to() {
+        let w = default_world();
+        let c = Camera::new(11, 7, PI/2.0)
+            .with_transform(Matrix4::view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0)));
+        let mut stream = PngStream::new(Vec::new(), 11, 7, true).unwrap();
+        c.render_to(&w, &mut stream).unwrap();
+        assert_eq!(stream.into_inner(), {
+            let mut whole = PngStream::new(Vec::new(), 11, 7, true).unwrap();
+            c.render(&w).write_rows(&mut whole).unwrap();
+            whole.into_inner()
+        });
+        let mut small = PngStream::new(Vec::new(), 10, 7, true).unwrap();
+        assert!(c.render_to(&w, &mut small).is_err());
+    }
+
+    #[test]
+    fn render_golden() {
+        let w = default_world();
+        let c = Camera::new(48, 32, PI/3.0)
+            .with_transform(Matrix4::view_transform(
+                point(1.0, 1.5, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0)));
+        assert_golden("default_world", &c.render(&w), 1.0/255.0);
+    }
+}
@@ -1,9 +1,50 @@
 use std::ops::{Add,Sub,Mul};
-use std::io;
-use std::io::Write;
-use std::fs::File;
+use std::fmt;
 use crate::maths;
 
+mod zlib;
+mod bmp;
+mod ppm;
+mod png;
+mod output;
+mod hdr;
+mod exr;
+mod tonemap;
+mod alpha;
+mod packed;
+mod diff;
+mod draw;
+mod font;
+mod iter;
+mod filter;
+mod resample;
+mod colour_space;
+mod spectrum;
+mod ansi;
+mod animation;
+mod gif;
+mod stream;
+pub use bmp::{BmpDepth,BmpOptions,BmpOrientation,BmpStream};
+pub use ppm::{PpmFormat,PpmStream};
+pub use png::PngStream;
+pub use output::{OutputTransform,Encoding,Dither};
+pub use exr::{ExrImage,ExrPixelType,ExrCompression};
+pub use tonemap::Tonemap;
+pub use alpha::{Rgba,Composite};
+pub use packed::PixelFormat;
+pub use diff::Diff;
+pub use font::{text_size,GLYPH_WIDTH,GLYPH_HEIGHT};
+pub use iter::TileMut;
+pub use filter::Kernel;
+pub use resample::ResizeFilter;
+pub use colour_space::{Hsv,Hsl,Xyz,Lab};
+pub use spectrum::{Spectrum,colour_matching,planck,cauchy,WAVELENGTH_MIN,WAVELENGTH_MAX,SPECTRUM_SAMPLES};
+pub use ansi::AnsiColours;
+pub use animation::{Animation,FrameFormat,Y4mChroma};
+pub use stream::{RowWriter,TileAssembler};
+#[cfg(test)]
+pub(crate) use diff::test::assert_golden;
+
 #[derive(Debug,Clone,Copy)]
 pub struct Colour {
     pub red: f64,
@@ -14,14 +55,17 @@ pub struct Colour {
 pub struct Canvas {
     width: usize,
     height: usize,
+    /// Colours are premultiplied by the matching coverage in `alpha`.
     pixels: Vec<Colour>,
+    alpha: Vec<f64>,
+    output: OutputTransform,
 }
 
 impl PartialEq for Colour {
     fn eq(&self, other: &Self) -> bool {
-        return maths::almost_same(self.red, other.red)
+        maths::almost_same(self.red, other.red)
             && maths::almost_same(self.blue, other.blue) 
-            && maths::almost_same(self.green, other.green) 
+            && maths::almost_same(self.green, other.green)
     }
 }
 
@@ -77,117 +121,90 @@ impl Colour {
     pub fn new(red: f64, green: f64, blue: f64) -> Self {
         Self {red, green, blue}
     }
+}
 
-    pub fn to_rgba(&self) -> u32 {
-        (Self::to_255(self.red) << 8) |
-            (Self::to_255(self.green) << 16) |
-            (Self::to_255(self.blue) << 24) |
-            255 // alpha = 1 for now
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width, height,
+            pixels: vec![Colour::new(0.0,0.0,0.0); width*height],
+            alpha: vec![1.0; width*height],
+            output: OutputTransform::default(),
+        }
     }
 
-    fn write(&self, file: &mut File) -> io::Result<()> {
-        write_u8(file, Self::to_255_u8(self.blue))?;
-        write_u8(file, Self::to_255_u8(self.green))?;
-        write_u8(file, Self::to_255_u8(self.red))
+    pub fn width(&self) -> usize {
+        self.width
     }
 
-    fn to_255(x: f64) -> u32 {
-        (x * 255.0) as u32
+    pub fn height(&self) -> usize {
+        self.height
     }
 
-    fn to_255_u8(x: f64) -> u8 {
-        (x * 255.0) as u8
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some(y*self.width + x)
+        } else {
+            None
+        }
     }
-}
 
-impl Canvas {
-    pub fn new(width: usize, height: usize) -> Self {
-        Self {
-            width, height,
-            pixels: vec![Colour::new(0.0,0.0,0.0); width*height],
+    /// Index of `(x, y)` in the pixel buffer, panicking when it lies
+    /// outside the canvas rather than wrapping onto another row.
+    fn checked_index(&self, x: usize, y: usize) -> usize {
+        match self.index(x, y) {
+            Some(i) => i,
+            None => panic!("{}", OutOfBounds {x, y, width: self.width, height: self.height}),
         }
     }
 
     pub fn pixel_at(&self, x: usize, y: usize) -> Colour {
-        self.pixels[y*self.width + x]
+        self.pixels[self.checked_index(x, y)]
     }
 
+    /// Writes an opaque pixel; see `write_rgba` for transparency.
     pub fn write_pixel(&mut self, x: usize, y: usize, c: Colour) {
-        self.pixels[y*self.width + x] = c;
-    }
-
-    pub fn to_bmp(&self, filename: &str) -> io::Result<()> {
-        let mut file = File::create(filename)?;
-
-        // BMP header
-        write_u8(&mut file, 0x42)?;
-        write_u8(&mut file, 0x4d)?;
-        let bmp_header_size = 14;
-        let dib_header_size = 40;
-        let pixel_array_offset = bmp_header_size + dib_header_size;
-        let bitmap_size = self.bmp_size();
-        let bmp_size = pixel_array_offset + bitmap_size;
-
-        write_u32(&mut file, bmp_size as u32)?;
-        write_u32(&mut file, 0)?;
-        write_u32(&mut file, pixel_array_offset as u32)?;
-
-        // DIB header
-        write_u32(&mut file, dib_header_size as u32)?;
-        write_i32(&mut file, self.width as i32)?;
-        write_i32(&mut file, self.height as i32)?;
-        write_u16(&mut file, 1)?;
-        write_u16(&mut file, 24)?; // bits per pixel
-        write_u32(&mut file, 0)?;  // RGB format
-        write_u32(&mut file, bitmap_size as u32)?;
-        write_u32(&mut file, 2835)?; // resolution, 72 dpi
-        write_u32(&mut file, 2835)?; // resolution, 72 dpi
-        write_i32(&mut file, 0)?;  // spurious
-        write_i32(&mut file, 0)?;  // spurious
-
-        // Bitmap data
-        for y in 0..self.height {
-            for x in 0..self.width {
-                self.pixel_at(x, y).write(&mut file)?;
-            }
-            for _ in 0..self.bmp_padding() {
-                write_u8(&mut file, 0)?;
-            }
-        }
-
-        Ok(())
+        let i = self.checked_index(x, y);
+        self.pixels[i] = c;
+        self.alpha[i] = 1.0;
     }
 
-    fn bmp_padding(&self) -> usize {
-        let row_size = self.width * 3;
-        let rem = row_size % 4;
-        if rem == 0 {
-            0
-        } else {
-            4 - rem
-        }
+    pub fn get(&self, x: usize, y: usize) -> Option<&Colour> {
+        self.index(x, y).map(|i| &self.pixels[i])
     }
 
-    fn bmp_size(&self) -> usize {
-        (self.width*3 + self.bmp_padding())*self.height
+    /// The premultiplied colour of pixel `(x, y)`; its alpha is left
+    /// alone.
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<&mut Colour> {
+        self.index(x, y).map(move |i| &mut self.pixels[i])
     }
-}
 
-fn write_u8(file: &mut File, val: u8) -> io::Result<()> {
-    file.write_all(&u8::to_le_bytes(val))
+    /// As `write_pixel`, but reports coordinates outside the canvas.
+    pub fn try_write_pixel(&mut self, x: usize, y: usize, c: Colour) -> Result<(), OutOfBounds> {
+        let i = self.index(x, y)
+            .ok_or(OutOfBounds {x, y, width: self.width, height: self.height})?;
+        self.pixels[i] = c;
+        self.alpha[i] = 1.0;
+        Ok(())
+    }
 }
 
-fn write_u16(file: &mut File, val: u16) -> io::Result<()> {
-    file.write_all(&u16::to_le_bytes(val))
+/// A pixel coordinate that lies outside a canvas.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct OutOfBounds {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
 }
 
-fn write_u32(file: &mut File, val: u32) -> io::Result<()> {
-    file.write_all(&u32::to_le_bytes(val))
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pixel ({}, {}) is outside the {}x{} canvas", self.x, self.y, self.width, self.height)
+    }
 }
 
-fn write_i32(file: &mut File, val: i32) -> io::Result<()> {
-    file.write_all(&i32::to_le_bytes(val))
-}
+impl std::error::Error for OutOfBounds {}
 
 #[cfg(test)]
 mod test {
@@ -211,5 +228,34 @@ mod test {
         assert_eq!(c.pixel_at(55, 190), Colour::new(1.0, 0.5, 0.5));
         assert_eq!(c.pixel_at(54, 190), Colour::new(0.0, 0.0, 0.0));
         assert_eq!(c.pixel_at(639, 479), Colour::new(0.0, 0.0, 0.0));
+        assert_eq!(c.width(), 640);
+        assert_eq!(c.height(), 480);
+    }
+
+    #[test]
+    fn checked_access() {
+        let mut c = Canvas::new(4, 3);
+        assert_eq!(c.get(3, 2), Some(&Colour::new(0.0, 0.0, 0.0)));
+        assert_eq!(c.get(4, 0), None);
+        assert_eq!(c.get(0, 3), None);
+
+        *c.get_mut(1, 1).unwrap() = Colour::new(0.5, 0.5, 0.5);
+        assert_eq!(c.pixel_at(1, 1), Colour::new(0.5, 0.5, 0.5));
+        assert!(c.get_mut(usize::MAX, 0).is_none());
+
+        assert_eq!(c.try_write_pixel(2, 2, Colour::new(1.0, 0.0, 0.0)), Ok(()));
+        assert_eq!(c.pixel_at(2, 2), Colour::new(1.0, 0.0, 0.0));
+        let err = c.try_write_pixel(4, 0, Colour::new(1.0, 0.0, 0.0)).unwrap_err();
+        assert_eq!(err, OutOfBounds {x: 4, y: 0, width: 4, height: 3});
+        assert_eq!(err.to_string(), "Pixel (4, 0) is outside the 4x3 canvas");
+        // nothing wrapped onto the next row
+        assert_eq!(c.pixel_at(0, 1), Colour::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Pixel (4, 0) is outside the 4x3 canvas")]
+    fn write_outside() {
+        let mut c = Canvas::new(4, 3);
+        c.write_pixel(4, 0, Colour::new(1.0, 1.0, 1.0));
     }
 }
\ No newline at end of file
@@ -0,0 +1,189 @@
+use super::{Canvas,Colour,OutputTransform};
+use crate::maths;
+
+/// A colour with coverage, stored premultiplied: `red`, `green` and
+/// `blue` already include the factor `alpha`.
+#[derive(Debug,Clone,Copy)]
+pub struct Rgba {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    pub alpha: f64,
+}
+
+impl PartialEq for Rgba {
+    fn eq(&self, other: &Self) -> bool {
+        maths::almost_same(self.red, other.red)
+            && maths::almost_same(self.green, other.green)
+            && maths::almost_same(self.blue, other.blue)
+            && maths::almost_same(self.alpha, other.alpha)
+    }
+}
+
+/// Porter–Duff operators, read as "source op destination".
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Composite {
+    Over,
+    In,
+    Out,
+    Atop,
+    Xor,
+}
+
+impl Rgba {
+    pub fn new(red: f64, green: f64, blue: f64, alpha: f64) -> Self {
+        Self {red, green, blue, alpha}
+    }
+
+    pub fn transparent() -> Self {
+        Self::new(0.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Premultiplies a straight colour by `alpha`.
+    pub fn from_colour(c: Colour, alpha: f64) -> Self {
+        Self::new(c.red * alpha, c.green * alpha, c.blue * alpha, alpha)
+    }
+
+    /// The premultiplied colour, i.e. as seen over black.
+    pub fn colour(&self) -> Colour {
+        Colour::new(self.red, self.green, self.blue)
+    }
+
+    /// The straight colour; black where fully transparent.
+    pub fn unpremultiplied(&self) -> Colour {
+        if self.alpha <= 0.0 {
+            Colour::new(0.0, 0.0, 0.0)
+        } else {
+            self.colour() * (1.0 / self.alpha)
+        }
+    }
+
+    /// `self op dst`, weighting the source by `fa` and the destination
+    /// by `fb` as in Porter and Duff's table.
+    pub fn composite(&self, dst: Rgba, op: Composite) -> Rgba {
+        let (fa, fb) = match op {
+            Composite::Over => (1.0, 1.0 - self.alpha),
+            Composite::In => (dst.alpha, 0.0),
+            Composite::Out => (1.0 - dst.alpha, 0.0),
+            Composite::Atop => (dst.alpha, 1.0 - self.alpha),
+            Composite::Xor => (1.0 - dst.alpha, 1.0 - self.alpha),
+        };
+        Rgba::new(
+            self.red*fa + dst.red*fb,
+            self.green*fa + dst.green*fb,
+            self.blue*fa + dst.blue*fb,
+            self.alpha*fa + dst.alpha*fb)
+    }
+}
+
+impl Canvas {
+    pub fn alpha_at(&self, x: usize, y: usize) -> f64 {
+        self.alpha[self.checked_index(x, y)]
+    }
+
+    /// Sets the coverage of pixel `(x, y)`, leaving its premultiplied
+    /// colour as it is.
+    pub fn write_alpha(&mut self, x: usize, y: usize, alpha: f64) {
+        let i = self.checked_index(x, y);
+        self.alpha[i] = alpha;
+    }
+
+    pub fn rgba_at(&self, x: usize, y: usize) -> Rgba {
+        let c = self.pixel_at(x, y);
+        Rgba::new(c.red, c.green, c.blue, self.alpha_at(x, y))
+    }
+
+    pub fn write_rgba(&mut self, x: usize, y: usize, c: Rgba) {
+        let i = self.checked_index(x, y);
+        self.pixels[i] = c.colour();
+        self.alpha[i] = c.alpha;
+    }
+
+    /// True when no pixel has any transparency, so that writers can
+    /// leave out the alpha channel.
+    pub fn is_opaque(&self) -> bool {
+        self.alpha.iter().all(|&a| a >= 1.0)
+    }
+
+    /// Replaces every pixel with `src op self`.
+    pub fn composite(&mut self, src: &Canvas, op: Composite) {
+        assert!(src.width == self.width && src.height == self.height,
+            "Canvas sizes differ in Canvas::composite()");
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let c = src.rgba_at(x, y).composite(self.rgba_at(x, y), op);
+                self.write_rgba(x, y, c);
+            }
+        }
+    }
+
+    /// Straight (unpremultiplied) output samples and alpha of pixel
+    /// `(x, y)`, for the writers that store an alpha channel.
+    pub fn quantised_rgba(&self, x: usize, y: usize) -> [u8; 4] {
+        self.output.quantise_rgba(self.rgba_at(x, y), x, y)
+    }
+}
+
+impl OutputTransform {
+    /// Straight samples and alpha of `c` when it sits at pixel `(x, y)`.
+    pub fn quantise_rgba(&self, c: Rgba, x: usize, y: usize) -> [u8; 4] {
+        let [r, g, b] = self.quantise(c.unpremultiplied(), x, y);
+        let a = (c.alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+        [r, g, b, a]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn premultiplication() {
+        let c = Rgba::from_colour(Colour::new(1.0, 0.5, 0.0), 0.5);
+        assert_eq!(c, Rgba::new(0.5, 0.25, 0.0, 0.5));
+        assert_eq!(c.unpremultiplied(), Colour::new(1.0, 0.5, 0.0));
+        assert_eq!(Rgba::transparent().unpremultiplied(), Colour::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn porter_duff() {
+        let red = Rgba::from_colour(Colour::new(1.0, 0.0, 0.0), 0.5);
+        let blue = Rgba::from_colour(Colour::new(0.0, 0.0, 1.0), 0.75);
+        assert_eq!(red.composite(blue, Composite::Over), Rgba::new(0.5, 0.0, 0.375, 0.875));
+        assert_eq!(red.composite(blue, Composite::In), Rgba::new(0.375, 0.0, 0.0, 0.375));
+        assert_eq!(red.composite(blue, Composite::Out), Rgba::new(0.125, 0.0, 0.0, 0.125));
+        assert_eq!(red.composite(blue, Composite::Atop), Rgba::new(0.375, 0.0, 0.375, 0.75));
+        assert_eq!(red.composite(blue, Composite::Xor), Rgba::new(0.125, 0.0, 0.375, 0.5));
+
+        // an opaque source covers, a transparent one leaves the destination
+        let opaque = Rgba::from_colour(Colour::new(0.2, 0.4, 0.6), 1.0);
+        assert_eq!(opaque.composite(blue, Composite::Over), opaque);
+        assert_eq!(Rgba::transparent().composite(blue, Composite::Over), blue);
+    }
+
+    #[test]
+    fn canvas_alpha() {
+        let mut plate = Canvas::new(2, 1);
+        plate.write_pixel(0, 0, Colour::new(0.0, 1.0, 0.0));
+        plate.write_pixel(1, 0, Colour::new(0.0, 1.0, 0.0));
+        assert!(plate.is_opaque());
+
+        let mut render = Canvas::new(2, 1);
+        render.write_pixel(0, 0, Colour::new(1.0, 0.0, 0.0));
+        render.write_rgba(1, 0, Rgba::transparent());
+        assert!(!render.is_opaque());
+        assert_eq!(render.quantised_rgba(1, 0), [0, 0, 0, 0]);
+
+        plate.composite(&render, Composite::Over);
+        assert_eq!(plate.pixel_at(0, 0), Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(plate.pixel_at(1, 0), Colour::new(0.0, 1.0, 0.0));
+        assert!(plate.is_opaque());
+
+        // writing a colour makes the pixel opaque again
+        render.write_rgba(0, 0, Rgba::from_colour(Colour::new(1.0, 0.5, 0.0), 0.5));
+        assert_eq!(render.quantised_rgba(0, 0), [255, 128, 0, 128]);
+        assert_eq!(render.quantised_pixel(0, 0), [128, 64, 0]);
+        render.write_pixel(0, 0, Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(render.alpha_at(0, 0), 1.0);
+    }
+}
@@ -0,0 +1,242 @@
+use std::io;
+use std::io::Write;
+use std::fs::File;
+use super::{Canvas,PpmFormat};
+
+/// Still formats for `Animation::to_sequence`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum FrameFormat {
+    Bmp,
+    Png,
+    Ppm,
+    Hdr,
+}
+
+impl FrameFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            FrameFormat::Bmp => "bmp",
+            FrameFormat::Png => "png",
+            FrameFormat::Ppm => "ppm",
+            FrameFormat::Hdr => "hdr",
+        }
+    }
+}
+
+/// Chroma layouts for YUV4MPEG2 output.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Y4mChroma {
+    /// Chroma averaged over 2x2 blocks, which every encoder accepts.
+    C420,
+    /// Full resolution chroma.
+    C444,
+}
+
+/// Equally sized frames played at `fps` frames per second.
+pub struct Animation {
+    frames: Vec<Canvas>,
+    fps: u32,
+}
+
+impl Animation {
+    pub fn new(fps: u32) -> Self {
+        assert!(fps > 0, "Zero frame rate in Animation::new()");
+        Self {frames: Vec::new(), fps}
+    }
+
+    /// Appends a frame, which must be the size of the first one.
+    pub fn push(&mut self, frame: Canvas) {
+        if let Some(first) = self.frames.first() {
+            assert!(frame.width == first.width && frame.height == first.height,
+                "Frame size differs in Animation::push()");
+        }
+        self.frames.push(frame);
+    }
+
+    pub fn frames(&self) -> &[Canvas] {
+        &self.frames
+    }
+
+    pub fn fps(&self) -> u32 {
+        self.fps
+    }
+
+    fn size(&self) -> (usize, usize) {
+        self.frames.first().map_or((0, 0), |f| (f.width, f.height))
+    }
+
+    /// Writes each frame to `<prefix>0000.<ext>`, `<prefix>0001.<ext>`
+    /// and so on, with more digits when there are more frames, and
+    /// returns the file names.
+    pub fn to_sequence(&self, prefix: &str, format: FrameFormat) -> io::Result<Vec<String>> {
+        let digits = self.frames.len().saturating_sub(1).to_string().len().max(4);
+        let mut names = Vec::with_capacity(self.frames.len());
+        for (i, frame) in self.frames.iter().enumerate() {
+            let name = format!("{}{:0width$}.{}", prefix, i, format.extension(), width = digits);
+            match format {
+                FrameFormat::Bmp => frame.to_bmp(&name)?,
+                FrameFormat::Png => frame.to_png(&name)?,
+                FrameFormat::Ppm => frame.to_ppm(&name, PpmFormat::Binary)?,
+                FrameFormat::Hdr => frame.to_hdr(&name)?,
+            }
+            names.push(name);
+        }
+        Ok(names)
+    }
+
+    pub fn to_y4m(&self, filename: &str, chroma: Y4mChroma) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(filename)?);
+        self.write_y4m(&mut file, chroma)?;
+        file.flush()
+    }
+
+    pub fn y4m_bytes(&self, chroma: Y4mChroma) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_y4m(&mut out, chroma).expect("Writing to a Vec cannot fail");
+        out
+    }
+
+    /// An uncompressed YUV4MPEG2 stream, e.g. for piping into
+    /// `ffmpeg -i -`. Samples are BT.601 studio range, taken from each
+    /// frame's output transform.
+    pub fn write_y4m(&self, out: &mut impl Write, chroma: Y4mChroma) -> io::Result<()> {
+        let (width, height) = self.size();
+        let tag = match chroma {
+            Y4mChroma::C420 => "420jpeg",
+            Y4mChroma::C444 => "444",
+        };
+        writeln!(out, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C{}", width, height, self.fps, tag)?;
+        for frame in &self.frames {
+            out.write_all(b"FRAME\n")?;
+            let (y, u, v) = frame.ycbcr_planes(chroma);
+            out.write_all(&y)?;
+            out.write_all(&u)?;
+            out.write_all(&v)?;
+        }
+        Ok(())
+    }
+}
+
+/// BT.601 studio range Y'CbCr of 8-bit samples.
+fn ycbcr([r, g, b]: [u8; 3]) -> [f64; 3] {
+    let (r, g, b) = (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0);
+    [
+        16.0 + 65.481*r + 128.553*g + 24.966*b,
+        128.0 - 37.797*r - 74.203*g + 112.0*b,
+        128.0 + 112.0*r - 93.786*g - 18.214*b,
+    ]
+}
+
+impl Canvas {
+    fn ycbcr_planes(&self, chroma: Y4mChroma) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let samples: Vec<[f64; 3]> = (0..self.width*self.height)
+            .map(|i| ycbcr(self.quantised_pixel(i % self.width, i / self.width)))
+            .collect();
+        let byte = |v: f64| v.round().clamp(0.0, 255.0) as u8;
+        let y = samples.iter().map(|s| byte(s[0])).collect();
+        let (u, v) = match chroma {
+            Y4mChroma::C444 => (
+                samples.iter().map(|s| byte(s[1])).collect(),
+                samples.iter().map(|s| byte(s[2])).collect()),
+            Y4mChroma::C420 => {
+                let (w, h) = (self.width.div_ceil(2), self.height.div_ceil(2));
+                let mut u = Vec::with_capacity(w*h);
+                let mut v = Vec::with_capacity(w*h);
+                for cy in 0..h {
+                    for cx in 0..w {
+                        // average the block, clipped at odd edges
+                        let (mut su, mut sv, mut n) = (0.0, 0.0, 0.0);
+                        for y in 2*cy..(2*cy + 2).min(self.height) {
+                            for x in 2*cx..(2*cx + 2).min(self.width) {
+                                let s = samples[y*self.width + x];
+                                su += s[1];
+                                sv += s[2];
+                                n += 1.0;
+                            }
+                        }
+                        u.push(byte(su / n));
+                        v.push(byte(sv / n));
+                    }
+                }
+                (u, v)
+            },
+        };
+        (y, u, v)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::Colour;
+
+    fn flat(width: usize, height: usize, c: Colour) -> Canvas {
+        let mut canvas = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                canvas.write_pixel(x, y, c);
+            }
+        }
+        canvas
+    }
+
+    #[test]
+    #[should_panic(expected = "Frame size differs")]
+    fn frames_must_match() {
+        let mut a = Animation::new(24);
+        a.push(Canvas::new(4, 4));
+        a.push(Canvas::new(4, 5));
+    }
+
+    #[test]
+    fn sequence() {
+        let dir = std::env::temp_dir().join(format!("raytracer-sequence-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let prefix = format!("{}/frame", dir.display());
+        let mut a = Animation::new(24);
+        for i in 0..3 {
+            a.push(flat(2, 2, Colour::new(i as f64 / 2.0, 0.0, 0.0)));
+        }
+        let names = a.to_sequence(&prefix, FrameFormat::Png).unwrap();
+        assert_eq!(names, vec![
+            format!("{}0000.png", prefix), format!("{}0001.png", prefix), format!("{}0002.png", prefix)]);
+        let last = Canvas::from_png(&names[2]).unwrap();
+        assert_eq!(last.quantised_pixel(1, 1), [255, 0, 0]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn y4m() {
+        let mut a = Animation::new(30);
+        a.push(flat(3, 3, Colour::new(1.0, 1.0, 1.0)));
+        a.push(flat(3, 3, Colour::new(0.0, 0.0, 0.0)));
+        let bytes = a.y4m_bytes(Y4mChroma::C420);
+        let header = b"YUV4MPEG2 W3 H3 F30:1 Ip A1:1 C420jpeg\n";
+        assert!(bytes.starts_with(header));
+        // 9 luma and 2x2 of each chroma per frame
+        let frame = 6 + 9 + 4 + 4;
+        assert_eq!(bytes.len(), header.len() + 2*frame);
+        let first = &bytes[header.len()..header.len() + frame];
+        assert_eq!(&first[..6], b"FRAME\n");
+        assert!(first[6..15].iter().all(|&y| y == 235));
+        assert!(first[15..].iter().all(|&c| c == 128));
+        assert!(bytes[header.len() + frame + 6..][..9].iter().all(|&y| y == 16));
+
+        let bytes = a.y4m_bytes(Y4mChroma::C444);
+        assert_eq!(bytes.len(), b"YUV4MPEG2 W3 H3 F30:1 Ip A1:1 C444\n".len() + 2*(6 + 27));
+    }
+
+    #[test]
+    fn chroma() {
+        let [y, u, v] = ycbcr([255, 0, 0]);
+        assert_eq!((y.round(), u.round(), v.round()), (81.0, 90.0, 240.0));
+
+        // red and blue halves average in 4:2:0
+        let mut c = flat(2, 1, Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel(1, 0, Colour::new(0.0, 0.0, 1.0));
+        let (y, u, v) = c.ycbcr_planes(Y4mChroma::C420);
+        assert_eq!((y.len(), u.len(), v.len()), (2, 1, 1));
+        assert_eq!(u[0], ((90.0 + 240.0) / 2.0_f64).round() as u8);
+        assert_eq!(v[0], ((240.0 + 110.0) / 2.0_f64).round() as u8);
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt::Write;
+use super::{Canvas,ResizeFilter};
+
+/// Colour escapes understood by the terminal.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum AnsiColours {
+    /// 24-bit `38;2;r;g;b` escapes.
+    TrueColour,
+    /// The xterm 256-colour palette: a 6x6x6 cube and a grey ramp.
+    Palette256,
+}
+
+impl AnsiColours {
+    /// True colour when `COLORTERM` says the terminal supports it, the
+    /// 256-colour palette otherwise.
+    pub fn detect() -> Self {
+        match std::env::var("COLORTERM") {
+            Ok(v) if v == "truecolor" || v == "24bit" => AnsiColours::TrueColour,
+            _ => AnsiColours::Palette256,
+        }
+    }
+
+    /// The escape parameters for `rgb`, as foreground (`38`) or
+    /// background (`48`).
+    fn code(self, layer: u8, [r, g, b]: [u8; 3]) -> String {
+        match self {
+            AnsiColours::TrueColour => format!("{};2;{};{};{}", layer, r, g, b),
+            AnsiColours::Palette256 => format!("{};5;{}", layer, palette_index([r, g, b])),
+        }
+    }
+}
+
+// channel levels of the xterm colour cube
+const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: [u8; 3], b: [u8; 3]) -> u32 {
+    a.iter().zip(&b).map(|(&a, &b)| (a as i32 - b as i32).pow(2) as u32).sum()
+}
+
+/// The nearest of the 240 palette entries above the 16 system colours,
+/// whose exact values vary between terminals.
+fn palette_index(rgb: [u8; 3]) -> u8 {
+    let level = |v: u8| (0..6).min_by_key(|&i| (CUBE[i] as i32 - v as i32).abs()).unwrap();
+    let [r, g, b] = [level(rgb[0]), level(rgb[1]), level(rgb[2])];
+    let cube = [CUBE[r], CUBE[g], CUBE[b]];
+
+    let mean = rgb.iter().map(|&v| v as u32).sum::<u32>() / 3;
+    let step = ((mean as i32 - 8 + 5) / 10).clamp(0, 23) as u8;
+    let grey = 8 + 10*step;
+
+    if distance(rgb, [grey; 3]) < distance(rgb, cube) {
+        232 + step
+    } else {
+        16 + 36*r as u8 + 6*g as u8 + b as u8
+    }
+}
+
+impl Canvas {
+    /// A preview for a terminal at most `max_cols` characters wide, in
+    /// true colour; see `to_ansi_with`.
+    pub fn to_ansi(&self, max_cols: usize) -> String {
+        self.to_ansi_with(max_cols, AnsiColours::TrueColour)
+    }
+
+    /// Draws the canvas with upper half blocks, each character cell
+    /// showing one pixel in its foreground and the one below in its
+    /// background. Wider canvases are shrunk to `max_cols` pixels
+    /// across, keeping the aspect ratio. Colours go through the output
+    /// transform as in the image writers.
+    pub fn to_ansi_with(&self, max_cols: usize, colours: AnsiColours) -> String {
+        let shrunk;
+        let canvas = if self.width > max_cols {
+            let height = (self.height * max_cols + self.width / 2) / self.width;
+            shrunk = self.resize(max_cols, height.max(1), ResizeFilter::Bilinear);
+            &shrunk
+        } else {
+            self
+        };
+
+        let mut out = String::new();
+        for y in (0..canvas.height).step_by(2) {
+            let mut last = (None, None);
+            for x in 0..canvas.width {
+                let top = canvas.quantised_pixel(x, y);
+                let bottom = if y + 1 < canvas.height {Some(canvas.quantised_pixel(x, y + 1))} else {None};
+                let mut codes = Vec::new();
+                if Some(top) != last.0 {
+                    codes.push(colours.code(38, top));
+                }
+                // an odd last row keeps the terminal's own background,
+                // as every row starts after a reset
+                if let Some(c) = bottom.filter(|&c| Some(c) != last.1) {
+                    codes.push(colours.code(48, c));
+                }
+                if !codes.is_empty() {
+                    write!(out, "\x1b[{}m", codes.join(";")).unwrap();
+                }
+                out.push('▀');
+                last = (Some(top), bottom);
+            }
+            out.push_str("\x1b[0m\n");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::Colour;
+
+    #[test]
+    fn palette() {
+        assert_eq!(palette_index([0, 0, 0]), 16);
+        assert_eq!(palette_index([255, 255, 255]), 231);
+        assert_eq!(palette_index([255, 0, 0]), 196);
+        assert_eq!(palette_index([0, 135, 255]), 16 + 2*6 + 5);
+        assert_eq!(palette_index([128, 128, 128]), 244);
+        assert_eq!(palette_index([8, 8, 8]), 232);
+        assert_eq!(AnsiColours::Palette256.code(48, [255, 0, 0]), "48;5;196");
+        assert_eq!(AnsiColours::TrueColour.code(38, [1, 2, 3]), "38;2;1;2;3");
+    }
+
+    #[test]
+    fn half_blocks() {
+        let mut c = Canvas::new(2, 3);
+        c.write_pixel(0, 0, Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel(1, 0, Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel(0, 1, Colour::new(0.0, 0.0, 1.0));
+        c.write_pixel(1, 2, Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(c.to_ansi(80), concat!(
+            "\x1b[38;2;255;0;0;48;2;0;0;255m▀\x1b[48;2;0;0;0m▀\x1b[0m\n",
+            "\x1b[38;2;0;0;0m▀\x1b[38;2;255;255;255m▀\x1b[0m\n"));
+        assert!(c.to_ansi_with(80, AnsiColours::Palette256).starts_with("\x1b[38;5;196;48;5;21m▀"));
+    }
+
+    #[test]
+    fn shrinks_to_fit() {
+        let c = Canvas::new(200, 100);
+        let preview = c.to_ansi(40);
+        let lines: Vec<&str> = preview.lines().collect();
+        // 40x20 pixels in 10 rows of cells
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|l| l.chars().filter(|&c| c == '▀').count() == 40));
+        assert_eq!(Canvas::new(3, 2).to_ansi(80).lines().count(), 1);
+    }
+}
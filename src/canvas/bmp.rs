@@ -0,0 +1,569 @@
+use std::io;
+use std::io::{Read,Write};
+use std::fs::File;
+use super::{Canvas,Colour,Rgba,OutputTransform};
+use super::stream::{RowWriter,RowCounter};
+
+const BMP_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: usize = 40;
+const V4_HEADER_SIZE: usize = 108;
+const V5_HEADER_SIZE: usize = 124;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const LCS_SRGB: u32 = 0x7352_4742; // 'sRGB'
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum BmpDepth {
+    /// 24-bit BGR with a BITMAPINFOHEADER, readable everywhere.
+    Bgr24,
+    /// 32-bit BGRA with a BITMAPV4HEADER whose masks declare the alpha
+    /// channel.
+    Bgra32,
+}
+
+/// Order of the rows in the file. Either way the image displays with
+/// canvas pixel `(0, 0)` at the top left.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum BmpOrientation {
+    /// Positive height, last canvas row first; what most readers expect.
+    BottomUp,
+    /// Negative height, first canvas row first.
+    TopDown,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct BmpOptions {
+    pub depth: BmpDepth,
+    pub orientation: BmpOrientation,
+}
+
+impl Default for BmpOptions {
+    fn default() -> Self {
+        Self {
+            depth: BmpDepth::Bgr24,
+            orientation: BmpOrientation::BottomUp,
+        }
+    }
+}
+
+impl Canvas {
+    pub fn to_bmp(&self, filename: &str) -> io::Result<()> {
+        self.to_bmp_with(filename, &BmpOptions::default())
+    }
+
+    pub fn to_bmp_with(&self, filename: &str, options: &BmpOptions) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(filename)?);
+        self.write_bmp(&mut file, options)?;
+        file.flush()
+    }
+
+    pub fn bmp_bytes(&self, options: &BmpOptions) -> Vec<u8> {
+        let mut out = Vec::with_capacity(V4_HEADER_SIZE + BMP_HEADER_SIZE + self.bmp_size(options.depth));
+        self.write_bmp(&mut out, options).expect("Canvas too large for BMP in Canvas::bmp_bytes()");
+        out
+    }
+
+    fn write_bmp<W: Write>(&self, file: &mut W, options: &BmpOptions) -> io::Result<()> {
+        write_bmp_header(file, self.width, self.height, options)?;
+        let mut row = Vec::with_capacity(self.width*bytes_per_pixel(options.depth) + 3);
+        for i in 0..self.height {
+            let y = match options.orientation {
+                BmpOrientation::BottomUp => self.height - 1 - i,
+                BmpOrientation::TopDown => i,
+            };
+            row.clear();
+            for x in 0..self.width {
+                push_bmp_pixel(&mut row, options.depth, &self.output, self.rgba_at(x, y), x, y);
+            }
+            row.resize(row.len() + bmp_padding(self.width, options.depth), 0);
+            file.write_all(&row)?;
+        }
+        Ok(())
+    }
+
+    fn bmp_size(&self, depth: BmpDepth) -> usize {
+        bitmap_size(self.width, self.height, depth).unwrap_or(0)
+    }
+
+    pub fn from_bmp(filename: &str) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(filename)?.read_to_end(&mut bytes)?;
+        Self::from_bmp_bytes(&bytes)
+    }
+
+    /// Reads uncompressed 24- and 32-bit images, stored bottom-up or
+    /// top-down, with a BITMAPINFOHEADER or any of its later versions.
+    /// Alpha is kept, premultiplied, when the header declares an alpha
+    /// mask.
+    pub fn from_bmp_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < BMP_HEADER_SIZE + INFO_HEADER_SIZE || &bytes[0..2] != b"BM" {
+            return Err(invalid("Not a BMP file"));
+        }
+        let pixel_array_offset = read_u32(bytes, 10)? as usize;
+        let dib_header_size = read_u32(bytes, 14)? as usize;
+        // 52 and 56 are the rarely seen Adobe V2/V3 extensions
+        if !matches!(dib_header_size, INFO_HEADER_SIZE | 52 | 56 | V4_HEADER_SIZE | V5_HEADER_SIZE) {
+            return Err(invalid("Unsupported BMP header version"));
+        }
+        let width = read_i32(bytes, 18)?;
+        let height = read_i32(bytes, 22)?;
+        let bits_per_pixel = read_u16(bytes, 28)?;
+        let compression = read_u32(bytes, 30)?;
+        if width <= 0 || height == 0 {
+            return Err(invalid("Invalid BMP dimensions"));
+        }
+
+        let masks = match (bits_per_pixel, compression) {
+            (24, BI_RGB) => None,
+            (32, BI_RGB) => Some([0x00ff_0000, 0x0000_ff00, 0x0000_00ff, 0]),
+            (32, BI_BITFIELDS) => {
+                // The masks live inside V4/V5 headers, or straight after
+                // a plain BITMAPINFOHEADER; only V3 and later have alpha
+                let pos = BMP_HEADER_SIZE + INFO_HEADER_SIZE;
+                let alpha = if dib_header_size >= 56 {read_u32(bytes, pos + 12)?} else {0};
+                Some([read_u32(bytes, pos)?, read_u32(bytes, pos + 4)?, read_u32(bytes, pos + 8)?, alpha])
+            },
+            _ => return Err(invalid("Unsupported BMP pixel format")),
+        };
+
+        let width = width as usize;
+        let top_down = height < 0;
+        let height = height.unsigned_abs() as usize;
+        let depth = if bits_per_pixel == 24 {BmpDepth::Bgr24} else {BmpDepth::Bgra32};
+        let bpp = bytes_per_pixel(depth);
+        let row_size = width*bpp + bmp_padding(width, depth);
+        let data = bytes.get(pixel_array_offset..pixel_array_offset + row_size*height)
+            .ok_or_else(|| invalid("Truncated BMP pixel data"))?;
+
+        let mut canvas = Canvas::new(width, height);
+        for (row, line) in data.chunks_exact(row_size).enumerate() {
+            let y = if top_down {row} else {height - 1 - row};
+            for x in 0..width {
+                let p = &line[x*bpp..(x + 1)*bpp];
+                match masks {
+                    None => {
+                        let c = Colour::new(p[2] as f64 / 255.0, p[1] as f64 / 255.0, p[0] as f64 / 255.0);
+                        canvas.write_pixel(x, y, c);
+                    },
+                    Some(masks) => {
+                        let v = u32::from_le_bytes([p[0], p[1], p[2], p[3]]);
+                        let c = Colour::new(masked(v, masks[0]), masked(v, masks[1]), masked(v, masks[2]));
+                        canvas.write_pixel(x, y, c);
+                        if masks[3] != 0 {
+                            canvas.write_alpha(x, y, masked(v, masks[3]));
+                        }
+                    },
+                }
+            }
+        }
+        // Many writers leave the alpha bytes at zero; treat that as opaque
+        if canvas.alpha.iter().all(|&a| a == 0.0) {
+            canvas.alpha.iter_mut().for_each(|a| *a = 1.0);
+        } else {
+            for (p, &a) in canvas.pixels.iter_mut().zip(&canvas.alpha) {
+                *p = *p * a;
+            }
+        }
+        Ok(canvas)
+    }
+}
+
+fn bytes_per_pixel(depth: BmpDepth) -> usize {
+    match depth {
+        BmpDepth::Bgr24 => 3,
+        BmpDepth::Bgra32 => 4,
+    }
+}
+
+// Rows are padded to a multiple of 4 bytes
+fn bmp_padding(width: usize, depth: BmpDepth) -> usize {
+    let row_size = width * bytes_per_pixel(depth);
+    let rem = row_size % 4;
+    if rem == 0 {
+        0
+    } else {
+        4 - rem
+    }
+}
+
+/// Extracts the channel selected by `mask` and scales it to [0, 1].
+fn masked(value: u32, mask: u32) -> f64 {
+    if mask == 0 {
+        return 0.0;
+    }
+    let shift = mask.trailing_zeros();
+    let max = mask >> shift;
+    ((value & mask) >> shift) as f64 / max as f64
+}
+
+/// Size of the pixel array, or `None` if it overflows.
+fn bitmap_size(width: usize, height: usize, depth: BmpDepth) -> Option<usize> {
+    width.checked_mul(bytes_per_pixel(depth))?
+        .checked_add(bmp_padding(width, depth))?
+        .checked_mul(height)
+}
+
+/// File and DIB headers for an image with the given layout, failing if
+/// its sizes do not fit in the header fields.
+fn write_bmp_header<W: Write>(file: &mut W, width: usize, height: usize, options: &BmpOptions) -> io::Result<()> {
+    let dib_header_size = match options.depth {
+        BmpDepth::Bgr24 => INFO_HEADER_SIZE,
+        BmpDepth::Bgra32 => V4_HEADER_SIZE,
+    };
+    let pixel_array_offset = BMP_HEADER_SIZE + dib_header_size;
+    let bitmap_size = bitmap_size(width, height, options.depth)
+        .filter(|&size| size <= u32::MAX as usize - pixel_array_offset);
+    let bitmap_size = match bitmap_size {
+        Some(size) if width <= i32::MAX as usize && height <= i32::MAX as usize => size,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image too large for BMP")),
+    };
+    let bmp_size = pixel_array_offset + bitmap_size;
+
+    // BMP header
+    write_u8(file, 0x42)?;
+    write_u8(file, 0x4d)?;
+
+    write_u32(file, bmp_size as u32)?;
+    write_u32(file, 0)?;
+    write_u32(file, pixel_array_offset as u32)?;
+
+    // DIB header
+    write_u32(file, dib_header_size as u32)?;
+    write_i32(file, width as i32)?;
+    match options.orientation {
+        BmpOrientation::BottomUp => write_i32(file, height as i32)?,
+        BmpOrientation::TopDown => write_i32(file, -(height as i32))?,
+    }
+    write_u16(file, 1)?;
+    match options.depth {
+        BmpDepth::Bgr24 => {
+            write_u16(file, 24)?; // bits per pixel
+            write_u32(file, BI_RGB)?;
+        },
+        BmpDepth::Bgra32 => {
+            write_u16(file, 32)?;
+            write_u32(file, BI_BITFIELDS)?;
+        },
+    }
+    write_u32(file, bitmap_size as u32)?;
+    write_u32(file, 2835)?; // resolution, 72 dpi
+    write_u32(file, 2835)?; // resolution, 72 dpi
+    write_i32(file, 0)?;  // colours in palette
+    write_i32(file, 0)?;  // important colours
+    if options.depth == BmpDepth::Bgra32 {
+        write_u32(file, 0x00ff_0000)?; // red mask
+        write_u32(file, 0x0000_ff00)?; // green mask
+        write_u32(file, 0x0000_00ff)?; // blue mask
+        write_u32(file, 0xff00_0000)?; // alpha mask
+        write_u32(file, LCS_SRGB)?;
+        // endpoints and gamma are unused for sRGB
+        for _ in 0..12 {
+            write_u32(file, 0)?;
+        }
+    }
+    Ok(())
+}
+
+fn push_bmp_pixel(row: &mut Vec<u8>, depth: BmpDepth, output: &OutputTransform, c: Rgba, x: usize, y: usize) {
+    match depth {
+        BmpDepth::Bgr24 => {
+            let [r, g, b] = output.quantise(c.colour(), x, y);
+            row.extend_from_slice(&[b, g, r]);
+        },
+        BmpDepth::Bgra32 => {
+            // straight alpha, as Windows expects
+            let [r, g, b, a] = output.quantise_rgba(c, x, y);
+            row.extend_from_slice(&[b, g, r, a]);
+        },
+    }
+}
+
+/// Writes a top-down BMP row by row; see `RowWriter`.
+pub struct BmpStream<W: Write> {
+    out: W,
+    rows: RowCounter,
+    depth: BmpDepth,
+    output: OutputTransform,
+    row: Vec<u8>,
+}
+
+impl<W: Write> BmpStream<W> {
+    /// Writes the headers for a `width` by `height` image.
+    pub fn new(mut out: W, width: usize, height: usize, depth: BmpDepth) -> io::Result<Self> {
+        let options = BmpOptions {depth, orientation: BmpOrientation::TopDown};
+        let mut header = Vec::new();
+        write_bmp_header(&mut header, width, height, &options)?;
+        out.write_all(&header)?;
+        Ok(Self {
+            out, depth,
+            rows: RowCounter::new(width, height),
+            output: OutputTransform::default(),
+            row: Vec::new(),
+        })
+    }
+
+    pub fn with_output_transform(mut self, output: OutputTransform) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> RowWriter for BmpStream<W> {
+    fn width(&self) -> usize {
+        self.rows.width
+    }
+
+    fn height(&self) -> usize {
+        self.rows.height
+    }
+
+    fn write_rgba_row(&mut self, row: &[Rgba]) -> io::Result<()> {
+        let y = self.rows.next(row.len())?;
+        self.row.clear();
+        for (x, &c) in row.iter().enumerate() {
+            push_bmp_pixel(&mut self.row, self.depth, &self.output, c, x, y);
+        }
+        self.row.resize(self.row.len() + bmp_padding(row.len(), self.depth), 0);
+        self.out.write_all(&self.row)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.rows.check_complete()?;
+        self.out.flush()
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> io::Result<u16> {
+    let b = bytes.get(pos..pos + 2).ok_or_else(|| invalid("Truncated BMP header"))?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> io::Result<u32> {
+    let b = bytes.get(pos..pos + 4).ok_or_else(|| invalid("Truncated BMP header"))?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_i32(bytes: &[u8], pos: usize) -> io::Result<i32> {
+    read_u32(bytes, pos).map(|v| v as i32)
+}
+
+fn write_u8<W: Write>(file: &mut W, val: u8) -> io::Result<()> {
+    file.write_all(&u8::to_le_bytes(val))
+}
+
+fn write_u16<W: Write>(file: &mut W, val: u16) -> io::Result<()> {
+    file.write_all(&u16::to_le_bytes(val))
+}
+
+fn write_u32<W: Write>(file: &mut W, val: u32) -> io::Result<()> {
+    file.write_all(&u32::to_le_bytes(val))
+}
+
+fn write_i32<W: Write>(file: &mut W, val: i32) -> io::Result<()> {
+    file.write_all(&i32::to_le_bytes(val))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_canvas() -> Canvas {
+        let mut c = Canvas::new(5, 3);
+        c.write_pixel(0, 0, Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel(4, 0, Colour::new(0.0, 1.0, 0.0));
+        c.write_pixel(0, 2, Colour::new(0.0, 0.0, 1.0));
+        c.write_pixel(2, 1, Colour::new(1.0, 1.0, 1.0));
+        c
+    }
+
+    #[test]
+    fn bmp_layout() {
+        let c = test_canvas();
+        let bmp = c.bmp_bytes(&BmpOptions::default());
+        // 5 pixels of 3 bytes padded to 16 bytes per row
+        assert_eq!(bmp.len(), 14 + 40 + 16*3);
+        assert_eq!(read_u32(&bmp, 2).unwrap() as usize, bmp.len());
+
+        let bmp = c.bmp_bytes(&BmpOptions {depth: BmpDepth::Bgra32, ..BmpOptions::default()});
+        assert_eq!(bmp.len(), 14 + 108 + 20*3);
+        assert_eq!(read_u32(&bmp, 14).unwrap(), 108);
+        assert_eq!(read_u16(&bmp, 28).unwrap(), 32);
+        assert_eq!(read_u32(&bmp, 66).unwrap(), 0xff00_0000);
+        assert_eq!(bmp[14 + 108 + 3], 255);
+    }
+
+    fn all_options() -> Vec<BmpOptions> {
+        let mut options = Vec::new();
+        for depth in &[BmpDepth::Bgr24, BmpDepth::Bgra32] {
+            for orientation in &[BmpOrientation::BottomUp, BmpOrientation::TopDown] {
+                options.push(BmpOptions {depth: *depth, orientation: *orientation});
+            }
+        }
+        options
+    }
+
+    #[test]
+    fn bmp_orientation() {
+        let c = test_canvas();
+        for options in all_options() {
+            let bmp = c.bmp_bytes(&options);
+            // decode by hand rather than trusting from_bmp_bytes
+            let offset = read_u32(&bmp, 10).unwrap() as usize;
+            let height = read_i32(&bmp, 22).unwrap();
+            assert_eq!(height.unsigned_abs(), 3);
+            assert_eq!(height < 0, options.orientation == BmpOrientation::TopDown);
+            let bpp = bytes_per_pixel(options.depth);
+            let row_size = 5*bpp + bmp_padding(5, options.depth);
+            for y in 0..3 {
+                // a positive height means the first row stored is the bottom one
+                let row = if height > 0 {2 - y} else {y};
+                for x in 0..5 {
+                    let p = offset + row*row_size + x*bpp;
+                    let [r, g, b] = c.quantised_pixel(x, y);
+                    assert_eq!(&bmp[p..p + 3], &[b, g, r]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bmp_round_trip() {
+        let c = test_canvas();
+        for options in all_options() {
+            let back = Canvas::from_bmp_bytes(&c.bmp_bytes(&options)).unwrap();
+            assert_eq!(back.width, 5);
+            assert_eq!(back.height, 3);
+            for y in 0..3 {
+                for x in 0..5 {
+                    assert_eq!(back.pixel_at(x, y), c.pixel_at(x, y));
+                }
+            }
+        }
+    }
+
+    /// Builds a 2x2 image by hand with a header of `dib_size` bytes.
+    fn handmade(dib_size: usize, bits: u16, compression: u32, height: i32, rows: &[&[u8]]) -> Vec<u8> {
+        let mut bmp = b"BM".to_vec();
+        let data: Vec<u8> = rows.concat();
+        let offset = 14 + dib_size + if dib_size == 40 && compression == BI_BITFIELDS {12} else {0};
+        bmp.extend_from_slice(&((offset + data.len()) as u32).to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&(offset as u32).to_le_bytes());
+        bmp.extend_from_slice(&(dib_size as u32).to_le_bytes());
+        bmp.extend_from_slice(&2i32.to_le_bytes());
+        bmp.extend_from_slice(&height.to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&bits.to_le_bytes());
+        bmp.extend_from_slice(&compression.to_le_bytes());
+        bmp.resize(14 + 40, 0);
+        if compression == BI_BITFIELDS {
+            // RGBA as the masks: red in the low byte
+            for mask in &[0x0000_00ffu32, 0x0000_ff00, 0x00ff_0000, 0xff00_0000] {
+                bmp.extend_from_slice(&mask.to_le_bytes());
+            }
+            if dib_size == 40 {
+                bmp.truncate(14 + 40 + 12);
+            }
+        }
+        bmp.resize(offset, 0);
+        bmp.extend(data);
+        bmp
+    }
+
+    #[test]
+    fn bmp_read_variants() {
+        let red = Colour::new(1.0, 0.0, 0.0);
+        let blue = Colour::new(0.0, 0.0, 1.0);
+        let black = Colour::new(0.0, 0.0, 0.0);
+
+        // 24-bit, bottom-up: the first row in the file is the bottom one
+        let bgr = [&[0, 0, 255, 0, 0, 0, 0, 0][..], &[255, 0, 0, 0, 0, 0, 0, 0][..]];
+        let c = Canvas::from_bmp_bytes(&handmade(40, 24, BI_RGB, 2, &bgr)).unwrap();
+        assert_eq!(c.pixel_at(0, 1), red);
+        assert_eq!(c.pixel_at(0, 0), blue);
+        assert_eq!(c.pixel_at(1, 0), black);
+
+        // same data top-down
+        let c = Canvas::from_bmp_bytes(&handmade(40, 24, BI_RGB, -2, &bgr)).unwrap();
+        assert_eq!(c.pixel_at(0, 0), red);
+        assert_eq!(c.pixel_at(0, 1), blue);
+
+        // 32-bit with non-standard masks, in each header version
+        let rgba = [&[255, 0, 0, 255, 0, 0, 0, 0][..], &[0, 0, 255, 255, 0, 0, 0, 0][..]];
+        for dib_size in &[40, V4_HEADER_SIZE, V5_HEADER_SIZE] {
+            let c = Canvas::from_bmp_bytes(&handmade(*dib_size, 32, BI_BITFIELDS, -2, &rgba)).unwrap();
+            assert_eq!(c.pixel_at(0, 0), red);
+            assert_eq!(c.pixel_at(0, 1), blue);
+        }
+
+        // 32-bit BI_RGB is BGRX
+        let bgrx = [&[0, 0, 255, 0, 0, 0, 0, 0][..], &[255, 0, 0, 0, 0, 0, 0, 0][..]];
+        let c = Canvas::from_bmp_bytes(&handmade(40, 32, BI_RGB, -2, &bgrx)).unwrap();
+        assert_eq!(c.pixel_at(0, 0), red);
+        assert_eq!(c.pixel_at(0, 1), blue);
+    }
+
+    #[test]
+    fn bmp_alpha() {
+        let mut c = test_canvas();
+        c.write_rgba(1, 1, Rgba::from_colour(Colour::new(0.2, 0.4, 1.0), 0.6));
+        c.write_rgba(3, 1, Rgba::transparent());
+        let options = BmpOptions {depth: BmpDepth::Bgra32, ..BmpOptions::default()};
+        let back = Canvas::from_bmp_bytes(&c.bmp_bytes(&options)).unwrap();
+        assert_eq!(back.rgba_at(1, 1), Rgba::new(0.12, 0.24, 0.6, 0.6));
+        assert_eq!(back.rgba_at(3, 1), Rgba::transparent());
+        assert_eq!(back.rgba_at(0, 0), Rgba::new(1.0, 0.0, 0.0, 1.0));
+
+        // 24-bit output is the premultiplied colour, i.e. over black
+        let back = Canvas::from_bmp_bytes(&c.bmp_bytes(&BmpOptions::default())).unwrap();
+        assert!(back.is_opaque());
+        assert_eq!(back.quantised_pixel(1, 1), c.quantised_pixel(1, 1));
+
+        // an alpha mask with every alpha byte zero reads as opaque
+        let rgba = [&[255, 0, 0, 0, 0, 0, 0, 0][..], &[0, 0, 255, 0, 0, 0, 0, 0][..]];
+        let c = Canvas::from_bmp_bytes(&handmade(V4_HEADER_SIZE, 32, BI_BITFIELDS, -2, &rgba)).unwrap();
+        assert!(c.is_opaque());
+        assert_eq!(c.pixel_at(0, 0), Colour::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn bmp_invalid() {
+        assert!(Canvas::from_bmp_bytes(b"PNG").is_err());
+        let bmp = test_canvas().bmp_bytes(&BmpOptions::default());
+        assert!(Canvas::from_bmp_bytes(&bmp[..bmp.len() - 1]).is_err());
+        let rows = [&[0u8; 4][..], &[0u8; 4][..]];
+        assert!(Canvas::from_bmp_bytes(&handmade(40, 16, BI_RGB, 2, &rows)).is_err());
+    }
+
+    #[test]
+    fn bmp_stream() {
+        let mut c = test_canvas();
+        c.write_rgba(1, 1, Rgba::from_colour(Colour::new(0.2, 0.4, 0.6), 0.5));
+        for depth in &[BmpDepth::Bgr24, BmpDepth::Bgra32] {
+            let options = BmpOptions {depth: *depth, orientation: BmpOrientation::TopDown};
+            let mut stream = BmpStream::new(Vec::new(), 5, 3, *depth).unwrap();
+            c.write_rows(&mut stream).unwrap();
+            assert_eq!(stream.into_inner(), c.bmp_bytes(&options));
+        }
+
+        // 32768 x 32768 BGRA is 4 GiB, too much for the size fields
+        let err = BmpStream::new(Vec::new(), 1 << 15, 1 << 15, BmpDepth::Bgra32).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(BmpStream::new(Vec::new(), 1 << 31, 1, BmpDepth::Bgr24).is_err());
+        assert!(BmpStream::new(Vec::new(), 1, usize::MAX, BmpDepth::Bgr24).is_err());
+        assert!(BmpStream::new(Vec::new(), 1 << 14, 1 << 14, BmpDepth::Bgra32).is_ok());
+
+        let mut stream = BmpStream::new(Vec::new(), 5, 3, BmpDepth::Bgr24).unwrap();
+        assert!(stream.write_row(&[Colour::new(0.0, 0.0, 0.0); 4]).is_err());
+        assert!(stream.finish().is_err());
+    }
+}
@@ -0,0 +1,387 @@
+use super::Colour;
+
+/// Hue in degrees [0, 360), saturation and value in [0, 1] for colours
+/// inside the unit cube.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Hsv {
+    pub hue: f64,
+    pub saturation: f64,
+    pub value: f64,
+}
+
+/// Hue in degrees [0, 360), saturation and lightness in [0, 1] for
+/// colours inside the unit cube.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Hsl {
+    pub hue: f64,
+    pub saturation: f64,
+    pub lightness: f64,
+}
+
+/// CIE 1931 tristimulus values, `y` being luminance.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Xyz {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// CIELAB relative to the D65 white of linear sRGB: `l` runs from 0
+/// for black to 100 for white.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Lab {
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+impl Hsv {
+    pub fn new(hue: f64, saturation: f64, value: f64) -> Self {
+        Self {hue, saturation, value}
+    }
+}
+
+impl Hsl {
+    pub fn new(hue: f64, saturation: f64, lightness: f64) -> Self {
+        Self {hue, saturation, lightness}
+    }
+}
+
+impl Xyz {
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self {x, y, z}
+    }
+}
+
+impl Lab {
+    pub fn new(l: f64, a: f64, b: f64) -> Self {
+        Self {l, a, b}
+    }
+
+    /// CIE76 difference: plain distance in Lab.
+    pub fn delta_e_76(&self, other: &Lab) -> f64 {
+        ((self.l - other.l).powi(2) + (self.a - other.a).powi(2) + (self.b - other.b).powi(2)).sqrt()
+    }
+
+    /// CIEDE2000 difference, following Sharma, Wu and Dalal's notes.
+    /// About 1 is the smallest difference most people notice.
+    pub fn delta_e(&self, other: &Lab) -> f64 {
+        let (l1, a1, b1) = (self.l, self.a, self.b);
+        let (l2, a2, b2) = (other.l, other.a, other.b);
+        let pow7 = |c: f64| { let c7 = c.powi(7); c7 / (c7 + 25f64.powi(7)) };
+
+        let c_mean = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+        let g = 0.5 * (1.0 - pow7(c_mean).sqrt());
+        let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+        let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+        let angle = |b: f64, a: f64| if a == 0.0 && b == 0.0 {0.0} else {b.atan2(a).to_degrees().rem_euclid(360.0)};
+        let (h1, h2) = (angle(b1, a1), angle(b2, a2));
+
+        let dl = l2 - l1;
+        let dc = c2 - c1;
+        let dh = if c1*c2 == 0.0 {
+            0.0
+        } else if h2 - h1 > 180.0 {
+            h2 - h1 - 360.0
+        } else if h2 - h1 < -180.0 {
+            h2 - h1 + 360.0
+        } else {
+            h2 - h1
+        };
+        let dh = 2.0 * (c1*c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+        let l_mean = (l1 + l2) / 2.0;
+        let c_mean = (c1 + c2) / 2.0;
+        let h_mean = if c1*c2 == 0.0 {
+            h1 + h2
+        } else if (h1 - h2).abs() <= 180.0 {
+            (h1 + h2) / 2.0
+        } else if h1 + h2 < 360.0 {
+            (h1 + h2 + 360.0) / 2.0
+        } else {
+            (h1 + h2 - 360.0) / 2.0
+        };
+        let cos = |degrees: f64| degrees.to_radians().cos();
+        let t = 1.0 - 0.17*cos(h_mean - 30.0) + 0.24*cos(2.0*h_mean)
+            + 0.32*cos(3.0*h_mean + 6.0) - 0.20*cos(4.0*h_mean - 63.0);
+        let theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+        let rt = -2.0 * pow7(c_mean).sqrt() * (2.0*theta).to_radians().sin();
+        let sl = 1.0 + 0.015*(l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+        let sc = 1.0 + 0.045*c_mean;
+        let sh = 1.0 + 0.015*c_mean*t;
+
+        let (l, c, h) = (dl / sl, dc / sc, dh / sh);
+        (l*l + c*c + h*h + rt*c*h).sqrt()
+    }
+}
+
+// Linear sRGB (Rec. 709 primaries, D65) to XYZ and back. The middle row
+// gives the Rec. 709 luminance weights.
+const TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124, 0.3576, 0.1805],
+    [0.2126, 0.7152, 0.0722],
+    [0.0193, 0.1192, 0.9505],
+];
+const FROM_XYZ: [[f64; 3]; 3] = [
+    [3.2406254773200533, -1.5372079722103187, -0.4986285986982479],
+    [-0.9689307147293194, 1.875756060885241, 0.04151752384295394],
+    [0.05571012044551061, -0.2040210505984867, 1.0569959422543882],
+];
+// XYZ of RGB white, so that white has a = b = 0 exactly
+const WHITE: [f64; 3] = [0.9505, 1.0, 1.089];
+
+fn transform(m: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        m[0][0]*v[0] + m[0][1]*v[1] + m[0][2]*v[2],
+        m[1][0]*v[0] + m[1][1]*v[1] + m[1][2]*v[2],
+        m[2][0]*v[0] + m[2][1]*v[1] + m[2][2]*v[2],
+    ]
+}
+
+/// The sRGB transfer function (IEC 61966-2-1) from linear to encoded.
+pub(crate) fn srgb_encode(v: f64) -> f64 {
+    if v <= 0.003_130_8 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0/2.4) - 0.055
+    }
+}
+
+pub(crate) fn srgb_decode(v: f64) -> f64 {
+    if v <= 0.040_45 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+const EPSILON: f64 = 216.0 / 24389.0;
+const KAPPA: f64 = 24389.0 / 27.0;
+
+fn lab_f(t: f64) -> f64 {
+    if t > EPSILON {
+        t.cbrt()
+    } else {
+        (KAPPA*t + 16.0) / 116.0
+    }
+}
+
+fn lab_f_inverse(t: f64) -> f64 {
+    if t*t*t > EPSILON {
+        t*t*t
+    } else {
+        (116.0*t - 16.0) / KAPPA
+    }
+}
+
+impl Colour {
+    /// Rec. 709 relative luminance of the linear colour.
+    pub fn luminance(&self) -> f64 {
+        let [r, g, b] = TO_XYZ[1];
+        r*self.red + g*self.green + b*self.blue
+    }
+
+    /// Gamma encodes each channel with the sRGB transfer function, as a
+    /// colour picker or an 8-bit file would show it.
+    pub fn to_srgb(&self) -> Colour {
+        Colour::new(srgb_encode(self.red), srgb_encode(self.green), srgb_encode(self.blue))
+    }
+
+    /// Decodes sRGB encoded channels, such as hex codes or values from
+    /// a paint program divided by 255, to linear light.
+    pub fn from_srgb(encoded: Colour) -> Colour {
+        Colour::new(srgb_decode(encoded.red), srgb_decode(encoded.green), srgb_decode(encoded.blue))
+    }
+
+    /// Hue, saturation and value of the channels as they are. Convert
+    /// with `to_srgb` first to match what paint programs show.
+    pub fn to_hsv(&self) -> Hsv {
+        let (max, min) = self.extremes();
+        let saturation = if max > 0.0 {(max - min) / max} else {0.0};
+        Hsv::new(self.hue(max, min), saturation, max)
+    }
+
+    pub fn from_hsv(hsv: Hsv) -> Colour {
+        let chroma = hsv.value * hsv.saturation;
+        Colour::from_hue(hsv.hue, chroma, hsv.value - chroma)
+    }
+
+    /// Hue, saturation and lightness of the channels as they are; see
+    /// `to_hsv`.
+    pub fn to_hsl(&self) -> Hsl {
+        let (max, min) = self.extremes();
+        let lightness = (max + min) / 2.0;
+        let saturation = if max > min {(max - min) / (1.0 - (2.0*lightness - 1.0).abs())} else {0.0};
+        Hsl::new(self.hue(max, min), saturation, lightness)
+    }
+
+    pub fn from_hsl(hsl: Hsl) -> Colour {
+        let chroma = (1.0 - (2.0*hsl.lightness - 1.0).abs()) * hsl.saturation;
+        Colour::from_hue(hsl.hue, chroma, hsl.lightness - chroma / 2.0)
+    }
+
+    pub fn to_xyz(&self) -> Xyz {
+        let [x, y, z] = transform(&TO_XYZ, [self.red, self.green, self.blue]);
+        Xyz::new(x, y, z)
+    }
+
+    pub fn from_xyz(xyz: Xyz) -> Colour {
+        let [r, g, b] = transform(&FROM_XYZ, [xyz.x, xyz.y, xyz.z]);
+        Colour::new(r, g, b)
+    }
+
+    pub fn to_lab(&self) -> Lab {
+        let xyz = self.to_xyz();
+        let fx = lab_f(xyz.x / WHITE[0]);
+        let fy = lab_f(xyz.y / WHITE[1]);
+        let fz = lab_f(xyz.z / WHITE[2]);
+        Lab::new(116.0*fy - 16.0, 500.0*(fx - fy), 200.0*(fy - fz))
+    }
+
+    pub fn from_lab(lab: Lab) -> Colour {
+        let fy = (lab.l + 16.0) / 116.0;
+        let fx = fy + lab.a / 500.0;
+        let fz = fy - lab.b / 200.0;
+        let y = if lab.l > KAPPA*EPSILON {fy*fy*fy} else {lab.l / KAPPA};
+        Colour::from_xyz(Xyz::new(
+            lab_f_inverse(fx) * WHITE[0],
+            y * WHITE[1],
+            lab_f_inverse(fz) * WHITE[2]))
+    }
+
+    /// Perceptual difference between two linear colours (CIEDE2000).
+    pub fn delta_e(&self, other: &Colour) -> f64 {
+        self.to_lab().delta_e(&other.to_lab())
+    }
+
+    fn extremes(&self) -> (f64, f64) {
+        let max = self.red.max(self.green).max(self.blue);
+        let min = self.red.min(self.green).min(self.blue);
+        (max, min)
+    }
+
+    /// Hue in degrees shared by HSV and HSL; 0 for greys.
+    fn hue(&self, max: f64, min: f64) -> f64 {
+        let delta = max - min;
+        if delta <= 0.0 {
+            return 0.0;
+        }
+        let sector = if max == self.red {
+            (self.green - self.blue) / delta
+        } else if max == self.green {
+            (self.blue - self.red) / delta + 2.0
+        } else {
+            (self.red - self.green) / delta + 4.0
+        };
+        (60.0 * sector).rem_euclid(360.0)
+    }
+
+    /// The colour with the given hue and chroma, lifted by `m`.
+    fn from_hue(hue: f64, chroma: f64, m: f64) -> Colour {
+        let h = hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        Colour::new(r + m, g + m, b + m)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maths::almost_same;
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() < tolerance
+    }
+
+    #[test]
+    fn hsv_and_hsl() {
+        let orange = Colour::new(1.0, 0.5, 0.0);
+        let hsv = orange.to_hsv();
+        assert!(almost_same(hsv.hue, 30.0));
+        assert!(almost_same(hsv.saturation, 1.0));
+        assert!(almost_same(hsv.value, 1.0));
+        let hsl = orange.to_hsl();
+        assert!(almost_same(hsl.hue, 30.0));
+        assert!(almost_same(hsl.saturation, 1.0));
+        assert!(almost_same(hsl.lightness, 0.5));
+
+        assert_eq!(Colour::from_hsv(Hsv::new(240.0, 0.5, 0.8)), Colour::new(0.4, 0.4, 0.8));
+        assert_eq!(Colour::from_hsl(Hsl::new(120.0, 1.0, 0.25)), Colour::new(0.0, 0.5, 0.0));
+        // hue wraps
+        assert_eq!(Colour::from_hsv(Hsv::new(-60.0, 1.0, 1.0)), Colour::new(1.0, 0.0, 1.0));
+
+        let grey = Colour::new(0.3, 0.3, 0.3).to_hsv();
+        assert_eq!((grey.hue, grey.saturation), (0.0, 0.0));
+        assert_eq!(Colour::new(0.0, 0.0, 0.0).to_hsl().saturation, 0.0);
+
+        for i in 0..64 {
+            let c = Colour::new((i % 4) as f64 / 3.0, (i / 4 % 4) as f64 / 3.0, (i / 16) as f64 / 3.0);
+            assert_eq!(Colour::from_hsv(c.to_hsv()), c);
+            assert_eq!(Colour::from_hsl(c.to_hsl()), c);
+        }
+    }
+
+    #[test]
+    fn srgb() {
+        assert!(almost_same(srgb_encode(0.5), 0.735_356_983_052_449_3));
+        assert!(close(Colour::new(0.5, 0.5, 0.5).to_srgb().red * 255.0, 187.5, 0.5));
+        let c = Colour::new(0.002, 0.2, 0.9);
+        assert_eq!(Colour::from_srgb(c.to_srgb()), c);
+    }
+
+    #[test]
+    fn xyz_and_lab() {
+        let white = Colour::new(1.0, 1.0, 1.0);
+        assert!(almost_same(white.luminance(), 1.0));
+        assert!(almost_same(Colour::new(0.0, 1.0, 0.0).luminance(), 0.7152));
+        assert!(almost_same(white.to_xyz().y, 1.0));
+
+        let lab = white.to_lab();
+        assert!(almost_same(lab.l, 100.0) && almost_same(lab.a, 0.0) && almost_same(lab.b, 0.0));
+        assert!(almost_same(Colour::new(0.0, 0.0, 0.0).to_lab().l, 0.0));
+        // middle grey is about L = 50
+        let grey = Colour::new(0.18418, 0.18418, 0.18418).to_lab();
+        assert!(close(grey.l, 50.0, 0.01));
+        // sRGB red, from published tables computed with the unrounded matrix
+        let red = Colour::new(1.0, 0.0, 0.0).to_lab();
+        assert!(close(red.l, 53.24, 0.01) && close(red.a, 80.09, 0.05) && close(red.b, 67.20, 0.05));
+
+        for c in &[Colour::new(0.2, 0.5, 0.9), Colour::new(0.001, 0.0, 0.003), Colour::new(2.0, 1.0, 0.5)] {
+            assert_eq!(Colour::from_xyz(c.to_xyz()), *c);
+            assert_eq!(Colour::from_lab(c.to_lab()), *c);
+        }
+    }
+
+    #[test]
+    fn delta_e() {
+        // pairs from Sharma, Wu and Dalal's CIEDE2000 test data
+        let pairs = [
+            ((50.0, 2.6772, -79.7751), (50.0, 0.0, -82.7485), 2.0425),
+            ((50.0, -1.3802, -84.2814), (50.0, 0.0, -82.7485), 1.0000),
+            ((50.0, 2.5, 0.0), (50.0, 3.1736, 0.5854), 1.0000),
+            ((50.0, 2.5, 0.0), (73.0, 25.0, -18.0), 27.1492),
+            ((50.0, 2.5, 0.0), (50.0, 0.0, -2.5), 4.3065),
+            ((60.2574, -34.0099, 36.2677), (60.4626, -34.1751, 39.4387), 1.2644),
+            ((2.0776, 0.0795, -1.1350), (0.9033, -0.0636, -0.5514), 0.9082),
+        ];
+        for &((l1, a1, b1), (l2, a2, b2), expected) in &pairs {
+            let (x, y) = (Lab::new(l1, a1, b1), Lab::new(l2, a2, b2));
+            assert!(close(x.delta_e(&y), expected, 1.0e-4), "{:?} {:?}", x, y);
+            assert!(close(y.delta_e(&x), expected, 1.0e-4));
+        }
+        assert!(almost_same(Lab::new(50.0, 0.0, 0.0).delta_e_76(&Lab::new(53.0, 4.0, 0.0)), 5.0));
+
+        let c = Colour::new(0.3, 0.6, 0.2);
+        assert_eq!(c.delta_e(&c), 0.0);
+        assert!(c.delta_e(&Colour::new(0.31, 0.6, 0.2)) < 1.0);
+        assert!(c.delta_e(&Colour::new(0.6, 0.3, 0.2)) > 10.0);
+    }
+}
@@ -0,0 +1,172 @@
+use super::{Canvas,Colour};
+
+/// Result of comparing two canvases channel by channel, alpha included.
+pub struct Diff {
+    /// Largest absolute difference in any channel.
+    pub max_error: f64,
+    /// Mean absolute difference over all channels.
+    pub mean_error: f64,
+    /// Peak signal-to-noise ratio in dB for a peak of 1.0; infinite
+    /// when the canvases are identical.
+    pub psnr: f64,
+    /// Pixels with some channel differing by more than the tolerance.
+    pub differing_pixels: usize,
+    /// False-colour map: pixels within tolerance show the reference as
+    /// dim grey, the others run from blue to red with their error
+    /// relative to `max_error`.
+    pub image: Canvas,
+}
+
+impl Diff {
+    pub fn is_match(&self) -> bool {
+        self.differing_pixels == 0
+    }
+}
+
+/// Blue, cyan, green, yellow, red as `t` goes from 0 to 1.
+fn heat(t: f64) -> Colour {
+    let stops = [
+        Colour::new(0.0, 0.0, 1.0),
+        Colour::new(0.0, 1.0, 1.0),
+        Colour::new(0.0, 1.0, 0.0),
+        Colour::new(1.0, 1.0, 0.0),
+        Colour::new(1.0, 0.0, 0.0),
+    ];
+    let t = t.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+    let i = usize::min(t.floor() as usize, stops.len() - 2);
+    let f = t - i as f64;
+    stops[i] * (1.0 - f) + stops[i + 1] * f
+}
+
+impl Canvas {
+    /// Compares `self`, the reference, with `other`. Channels differing
+    /// by no more than `tolerance` count as equal.
+    pub fn diff(&self, other: &Canvas, tolerance: f64) -> Diff {
+        assert!(self.width == other.width && self.height == other.height,
+            "Canvas sizes differ in Canvas::diff()");
+        let mut errors = Vec::with_capacity(self.pixels.len());
+        let mut sum = 0.0;
+        let mut sum_squares = 0.0;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (a, b) = (self.rgba_at(x, y), other.rgba_at(x, y));
+                let channels = [a.red - b.red, a.green - b.green, a.blue - b.blue, a.alpha - b.alpha];
+                let mut error: f64 = 0.0;
+                for d in &channels {
+                    error = error.max(d.abs());
+                    sum += d.abs();
+                    sum_squares += d*d;
+                }
+                errors.push(error);
+            }
+        }
+
+        let samples = (4*self.pixels.len()).max(1) as f64;
+        let mse = sum_squares / samples;
+        let max_error = errors.iter().cloned().fold(0.0, f64::max);
+        let mut image = Canvas::new(self.width, self.height);
+        let mut differing_pixels = 0;
+        for (i, &error) in errors.iter().enumerate() {
+            image.pixels[i] = if error > tolerance {
+                differing_pixels += 1;
+                heat(error / max_error)
+            } else {
+                let c = self.pixels[i];
+                let grey = 0.25 * c.luminance().clamp(0.0, 1.0);
+                Colour::new(grey, grey, grey)
+            };
+        }
+
+        Diff {
+            max_error,
+            mean_error: sum / samples,
+            psnr: if mse == 0.0 {f64::INFINITY} else {10.0 * f64::log10(1.0 / mse)},
+            differing_pixels,
+            image,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use super::super::Rgba;
+    use std::path::PathBuf;
+
+    /// Compares `canvas`, as it would be saved to PNG, with the reference
+    /// image `tests/golden/<name>.png`. Setting `UPDATE_GOLDEN` writes
+    /// the reference instead; a missing one fails. On a mismatch the
+    /// render and the false-colour diff are left in `target/golden/`.
+    pub(crate) fn assert_golden(name: &str, canvas: &Canvas, tolerance: f64) {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let golden = root.join("tests").join("golden").join(format!("{}.png", name));
+        let png = canvas.png_bytes();
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+            std::fs::write(&golden, &png).unwrap();
+            eprintln!("Wrote reference image {}", golden.display());
+            return;
+        }
+        assert!(golden.exists(), "{}: no reference image {}; run the test with UPDATE_GOLDEN=1 to create it",
+            name, golden.display());
+
+        let expected = Canvas::from_png(golden.to_str().unwrap()).unwrap();
+        let actual = Canvas::from_png_bytes(&png).unwrap();
+        assert!(expected.width == actual.width && expected.height == actual.height,
+            "{}: size {}x{} differs from reference {}x{}",
+            name, actual.width, actual.height, expected.width, expected.height);
+        let diff = expected.diff(&actual, tolerance);
+        if !diff.is_match() {
+            let out = root.join("target").join("golden");
+            std::fs::create_dir_all(&out).unwrap();
+            std::fs::write(out.join(format!("{}.png", name)), &png).unwrap();
+            diff.image.to_png(out.join(format!("{}.diff.png", name)).to_str().unwrap()).unwrap();
+            panic!("{}: {} pixels differ from reference, max error {:.4}, PSNR {:.1} dB; see {}",
+                name, diff.differing_pixels, diff.max_error, diff.psnr, out.display());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "UPDATE_GOLDEN=1")]
+    fn missing_golden() {
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            panic!("UPDATE_GOLDEN=1 would write the reference");
+        }
+        assert_golden("no_such_reference", &Canvas::new(1, 1), 0.0);
+    }
+
+    #[test]
+    fn identical() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(1, 1, Colour::new(0.2, 0.4, 0.6));
+        let diff = c.diff(&c, 0.0);
+        assert!(diff.is_match());
+        assert_eq!(diff.max_error, 0.0);
+        assert_eq!(diff.mean_error, 0.0);
+        assert_eq!(diff.psnr, f64::INFINITY);
+    }
+
+    #[test]
+    fn statistics() {
+        let a = Canvas::new(2, 2);
+        let mut b = Canvas::new(2, 2);
+        b.write_pixel(0, 0, Colour::new(0.5, 0.0, 0.0));
+        b.write_pixel(1, 0, Colour::new(0.0, 0.01, 0.0));
+        b.write_rgba(0, 1, Rgba::new(0.0, 0.0, 0.0, 0.75));
+
+        let diff = a.diff(&b, 0.02);
+        assert_eq!(diff.max_error, 0.5);
+        assert!(f64::abs(diff.mean_error - 0.76/16.0) < 1.0e-12);
+        let mse: f64 = (0.25 + 0.0001 + 0.0625) / 16.0;
+        assert!(f64::abs(diff.psnr - 10.0 * f64::log10(1.0 / mse)) < 1.0e-9);
+        assert_eq!(diff.differing_pixels, 2);
+        assert!(!diff.is_match());
+
+        // the worst pixel is red, pixels within tolerance are grey
+        assert_eq!(diff.image.pixel_at(0, 0), Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(diff.image.pixel_at(1, 0), Colour::new(0.0, 0.0, 0.0));
+        assert_eq!(diff.image.pixel_at(0, 1), heat(0.5));
+        assert_eq!(heat(0.5), Colour::new(0.0, 1.0, 0.0));
+        assert_eq!(heat(0.0), Colour::new(0.0, 0.0, 1.0));
+    }
+}
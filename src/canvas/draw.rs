@@ -0,0 +1,532 @@
+use super::{Canvas,Colour,Rgba,Composite};
+
+// Drawing primitives for overlays. Integer coordinates address pixels,
+// floating point ones treat pixel (x, y) as the square from (x, y) to
+// (x + 1, y + 1). Everything is clipped to the canvas.
+impl Canvas {
+    fn plot(&mut self, x: isize, y: isize, colour: Colour) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.write_pixel(x as usize, y as usize, colour);
+        }
+    }
+
+    /// Lays `colour` over the pixel with the given coverage.
+    fn blend(&mut self, x: isize, y: isize, colour: Colour, coverage: f64) {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            let (x, y) = (x as usize, y as usize);
+            let c = Rgba::from_colour(colour, coverage.clamp(0.0, 1.0));
+            self.write_rgba(x, y, c.composite(self.rgba_at(x, y), Composite::Over));
+        }
+    }
+
+    /// Fills pixels `x0..=x1` of row `y`.
+    fn span(&mut self, x0: isize, x1: isize, y: isize, colour: Colour) {
+        if y < 0 || y as usize >= self.height {
+            return;
+        }
+        let x0 = x0.max(0);
+        let x1 = x1.min(self.width as isize - 1);
+        for x in x0..=x1 {
+            self.write_pixel(x as usize, y as usize, colour);
+        }
+    }
+
+    /// Bresenham's line, both end points included.
+    pub fn draw_line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, colour: Colour) {
+        let inside = |x: isize, y: isize| x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height;
+        if !(inside(x0, y0) && inside(x1, y1)) {
+            // clip to just outside the canvas so that the loop stays short
+            // and the differences cannot overflow
+            let bounds = (-1.0, -1.0, self.width as f64, self.height as f64);
+            let r = |v: f64| v.round() as isize;
+            if let Some(((x0, y0), (x1, y1))) = clip_segment((x0 as f64, y0 as f64), (x1 as f64, y1 as f64), bounds) {
+                self.draw_clipped_line(r(x0), r(y0), r(x1), r(y1), colour);
+            }
+            return;
+        }
+        self.draw_clipped_line(x0, y0, x1, y1, colour);
+    }
+
+    fn draw_clipped_line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, colour: Colour) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 {1} else {-1};
+        let sy = if y0 < y1 {1} else {-1};
+        let mut err = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+        loop {
+            self.plot(x, y, colour);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2*err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Xiaolin Wu's anti-aliased line between points in pixel space.
+    /// Lines with non-finite end points are not drawn.
+    pub fn draw_line_aa(&mut self, x0: f64, y0: f64, x1: f64, y1: f64, colour: Colour) {
+        if ![x0, y0, x1, y1].iter().all(|v| v.is_finite()) {
+            return;
+        }
+        // leave room around the canvas for the end points' partial coverage
+        let bounds = (-2.0, -2.0, self.width as f64 + 2.0, self.height as f64 + 2.0);
+        let ((x0, y0), (x1, y1)) = match clip_segment((x0, y0), (x1, y1), bounds) {
+            Some(ends) => ends,
+            None => return,
+        };
+
+        // work with pixel centres at integer positions
+        let (mut x0, mut y0, mut x1, mut y1) = (x0 - 0.5, y0 - 0.5, x1 - 0.5, y1 - 0.5);
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        if steep {
+            std::mem::swap(&mut x0, &mut y0);
+            std::mem::swap(&mut x1, &mut y1);
+        }
+        if x0 > x1 {
+            std::mem::swap(&mut x0, &mut x1);
+            std::mem::swap(&mut y0, &mut y1);
+        }
+        let gradient = if x1 == x0 {1.0} else {(y1 - y0) / (x1 - x0)};
+        let plot = |canvas: &mut Canvas, x: f64, y: f64, coverage: f64| {
+            let (x, y) = (x as isize, y as isize);
+            if steep {
+                canvas.blend(y, x, colour, coverage);
+            } else {
+                canvas.blend(x, y, colour, coverage);
+            }
+        };
+
+        // end points get partial coverage along the line too
+        let xend = x0.round();
+        let yend = y0 + gradient * (xend - x0);
+        let xgap = 1.0 - (x0 + 0.5).fract();
+        let xpxl1 = xend;
+        plot(self, xpxl1, yend.floor(), (1.0 - yend.fract()) * xgap);
+        plot(self, xpxl1, yend.floor() + 1.0, yend.fract() * xgap);
+        let mut intery = yend + gradient;
+
+        let xend = x1.round();
+        let yend = y1 + gradient * (xend - x1);
+        let xgap = (x1 + 0.5).fract();
+        let xpxl2 = xend;
+        plot(self, xpxl2, yend.floor(), (1.0 - yend.fract()) * xgap);
+        plot(self, xpxl2, yend.floor() + 1.0, yend.fract() * xgap);
+
+        let mut x = xpxl1 + 1.0;
+        while x < xpxl2 {
+            plot(self, x, intery.floor(), 1.0 - intery.fract());
+            plot(self, x, intery.floor() + 1.0, intery.fract());
+            intery += gradient;
+            x += 1.0;
+        }
+    }
+
+    pub fn draw_rect(&mut self, x: isize, y: isize, width: usize, height: usize, colour: Colour) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let (x1, y1) = (far_edge(x, width), far_edge(y, height));
+        self.span(x, x1, y, colour);
+        self.span(x, x1, y1, colour);
+        let rows = y.saturating_add(1).max(0)..y1.min(self.height as isize);
+        for yy in rows {
+            self.plot(x, yy, colour);
+            self.plot(x1, yy, colour);
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, colour: Colour) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let x1 = far_edge(x, width);
+        for yy in y.max(0)..far_edge(y, height).saturating_add(1).min(self.height as isize) {
+            self.span(x, x1, yy, colour);
+        }
+    }
+
+    pub fn draw_circle(&mut self, cx: isize, cy: isize, radius: usize, colour: Colour) {
+        self.draw_ellipse(cx, cy, radius, radius, colour);
+    }
+
+    pub fn fill_circle(&mut self, cx: isize, cy: isize, radius: usize, colour: Colour) {
+        self.fill_ellipse(cx, cy, radius, radius, colour);
+    }
+
+    /// Outline of the ellipse with semi-axes `rx` and `ry`: the pixels
+    /// that `fill_ellipse` covers with a neighbour outside it.
+    pub fn draw_ellipse(&mut self, cx: isize, cy: isize, rx: usize, ry: usize, colour: Colour) {
+        for (y, dy) in self.ellipse_rows(cy, ry) {
+            let w = ellipse_half_width(rx, ry, dy);
+            let inner = w.min(ellipse_half_width(rx, ry, dy.abs() + 1) + 1);
+            let cx = cx as i128;
+            self.span(saturate(cx + inner), saturate(cx + w), y, colour);
+            self.span(saturate(cx - w), saturate(cx - inner), y, colour);
+        }
+    }
+
+    /// Fills the pixels whose centres lie within half a pixel of the
+    /// ellipse with semi-axes `rx` and `ry`.
+    pub fn fill_ellipse(&mut self, cx: isize, cy: isize, rx: usize, ry: usize, colour: Colour) {
+        for (y, dy) in self.ellipse_rows(cy, ry) {
+            let w = ellipse_half_width(rx, ry, dy);
+            self.span(saturate(cx as i128 - w), saturate(cx as i128 + w), y, colour);
+        }
+    }
+
+    /// The canvas rows an ellipse centred on row `cy` reaches, with their
+    /// offsets from the centre.
+    fn ellipse_rows(&self, cy: isize, ry: usize) -> impl Iterator<Item = (isize, i128)> {
+        let cy = cy as i128;
+        let top = (cy - ry as i128).max(0);
+        let bottom = (cy + ry as i128).min(self.height as i128 - 1);
+        (top..=bottom).map(move |y| (y as isize, y - cy))
+    }
+
+    /// Fills the pixels whose centres lie in the triangle, blending the
+    /// vertex colours with barycentric weights.
+    pub fn fill_triangle(&mut self, vertices: [(f64, f64); 3], colours: [Colour; 3]) {
+        let [a, b, c] = vertices;
+        let edge = |p: (f64, f64), q: (f64, f64), r: (f64, f64)| (q.0 - p.0)*(r.1 - p.1) - (q.1 - p.1)*(r.0 - p.0);
+        let area = edge(a, b, c);
+        if area == 0.0 {
+            return;
+        }
+        let Some((x0, x1, y0, y1)) = self.clipped_bounds(&vertices) else {
+            return;
+        };
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let p = (x as f64 + 0.5, y as f64 + 0.5);
+                let w0 = edge(b, c, p) / area;
+                let w1 = edge(c, a, p) / area;
+                let w2 = edge(a, b, p) / area;
+                if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
+                    let colour = colours[0]*w0 + colours[1]*w1 + colours[2]*w2;
+                    self.write_pixel(x, y, colour);
+                }
+            }
+        }
+    }
+
+    /// Scanline fill with the even-odd rule, sampling pixel centres.
+    pub fn fill_polygon(&mut self, points: &[(f64, f64)], colour: Colour) {
+        if points.len() < 3 {
+            return;
+        }
+        let Some((_, _, y0, y1)) = self.clipped_bounds(points) else {
+            return;
+        };
+        let mut crossings = Vec::new();
+        for y in y0..=y1 {
+            let yc = y as f64 + 0.5;
+            crossings.clear();
+            for (i, &p) in points.iter().enumerate() {
+                let q = points[(i + 1) % points.len()];
+                if (p.1 <= yc && yc < q.1) || (q.1 <= yc && yc < p.1) {
+                    crossings.push(p.0 + (yc - p.1) * (q.0 - p.0) / (q.1 - p.1));
+                }
+            }
+            crossings.sort_by(|a, b| a.total_cmp(b));
+            for pair in crossings.chunks_exact(2) {
+                // pixels whose centres lie in [left, right)
+                let left = (pair[0] - 0.5).ceil();
+                let right = (pair[1] - 0.5).ceil() - 1.0;
+                if left <= right {
+                    self.span(left.max(-1.0) as isize, right.min(self.width as f64) as isize, y as isize, colour);
+                }
+            }
+        }
+    }
+
+    /// Pixel bounding box of `points`, clipped to the canvas; `None`
+    /// when it misses the canvas altogether.
+    fn clipped_bounds(&self, points: &[(f64, f64)]) -> Option<(usize, usize, usize, usize)> {
+        let min_x = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min).floor().max(0.0);
+        let min_y = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min).floor().max(0.0);
+        let max_x = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max).ceil().min(self.width as f64 - 1.0);
+        let max_y = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max).ceil().min(self.height as f64 - 1.0);
+        if min_x > max_x || min_y > max_y {
+            return None;
+        }
+        Some((min_x as usize, max_x as usize, min_y as usize, max_y as usize))
+    }
+}
+
+/// Last pixel of a run of `len > 0` pixels from `start`, saturating at
+/// the edge of the coordinate space.
+fn far_edge(start: isize, len: usize) -> isize {
+    (start as i128 + len as i128 - 1).min(isize::MAX as i128) as isize
+}
+
+/// Clips the segment between `a` and `b` to the box `(min_x, min_y,
+/// max_x, max_y)`; `None` when it misses the box.
+fn clip_segment(a: (f64, f64), b: (f64, f64), (min_x, min_y, max_x, max_y): (f64, f64, f64, f64))
+    -> Option<((f64, f64), (f64, f64))> {
+    // far away end points leave thousands of pixels of rounding error in
+    // the result, so bring them within a wider box first
+    const MARGIN: f64 = 65536.0;
+    let (a, b) = liang_barsky(a, b, (min_x - MARGIN, min_y - MARGIN, max_x + MARGIN, max_y + MARGIN))?;
+    liang_barsky(a, b, (min_x, min_y, max_x, max_y))
+}
+
+fn liang_barsky(a: (f64, f64), b: (f64, f64), (min_x, min_y, max_x, max_y): (f64, f64, f64, f64))
+    -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0.0_f64, 1.0_f64);
+    for &(p, q) in &[(-dx, a.0 - min_x), (dx, max_x - a.0), (-dy, a.1 - min_y), (dy, max_y - a.1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    let at = |t: f64| (a.0 + t*dx, a.1 + t*dy);
+    Some((at(t0), at(t1)))
+}
+
+/// Largest `x` such that `(x, y)` is within half a pixel of the ellipse
+/// with semi-axes `rx` and `ry`, or -1 if row `y` misses it.
+fn ellipse_half_width(rx: usize, ry: usize, y: i128) -> i128 {
+    // (x / (rx + 1/2))^2 + (y / (ry + 1/2))^2 <= 1, doubled to stay in integers
+    let (a, b, y) = (2*rx as i128 + 1, 2*ry as i128 + 1, y.abs());
+    if 2*y >= b {
+        return -1;
+    }
+    let exact = a.checked_mul(a)
+        .and_then(|a2| b.checked_mul(b).map(|b2| (a2, b2)))
+        .and_then(|(a2, b2)| a2.checked_mul(b2 - 4*y*y));
+    match exact {
+        Some(n) => (n as u128).isqrt() as i128 / (2*b),
+        // radii beyond 2^30 or so, far larger than any canvas
+        None => {
+            let t = 2.0 * y as f64 / b as f64;
+            (a as f64 / 2.0 * f64::sqrt(1.0 - t*t)).floor() as i128
+        },
+    }
+}
+
+/// Clamps a coordinate to the range of `isize`.
+pub(super) fn saturate(v: i128) -> isize {
+    v.clamp(isize::MIN as i128, isize::MAX as i128) as isize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lit(c: &Canvas) -> Vec<(usize, usize)> {
+        let mut points = Vec::new();
+        for y in 0..c.height {
+            for x in 0..c.width {
+                if c.pixel_at(x, y) != Colour::new(0.0, 0.0, 0.0) {
+                    points.push((x, y));
+                }
+            }
+        }
+        points
+    }
+
+    const WHITE: Colour = Colour {red: 1.0, green: 1.0, blue: 1.0};
+
+    #[test]
+    fn bresenham() {
+        let mut c = Canvas::new(8, 8);
+        c.draw_line(0, 0, 3, 3, WHITE);
+        assert_eq!(lit(&c), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+
+        let mut c = Canvas::new(8, 8);
+        c.draw_line(5, 1, 0, 3, WHITE);
+        assert_eq!(lit(&c).len(), 6);
+        assert_eq!(c.pixel_at(0, 3), WHITE);
+        assert_eq!(c.pixel_at(5, 1), WHITE);
+
+        // steep, and clipped at both ends
+        let mut c = Canvas::new(4, 4);
+        c.draw_line(2, -10, 2, 10, WHITE);
+        assert_eq!(lit(&c), vec![(2, 0), (2, 1), (2, 2), (2, 3)]);
+        c.draw_line(-5, -5, -1, 20, WHITE);
+        assert_eq!(lit(&c).len(), 4);
+
+        // extreme end points are clipped before stepping
+        let mut c = Canvas::new(4, 4);
+        c.draw_line(0, 0, isize::MAX, 0, WHITE);
+        assert_eq!(lit(&c), vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+        c.draw_line(isize::MIN, 1, isize::MAX, 1, WHITE);
+        assert_eq!(lit(&c).len(), 8);
+        // far end points lose precision, but must not hang or overflow
+        c.draw_line(isize::MIN, isize::MIN, isize::MAX, isize::MAX, WHITE);
+    }
+
+    #[test]
+    fn wu() {
+        // a horizontal line through pixel centres is solid
+        let mut c = Canvas::new(8, 4);
+        c.draw_line_aa(1.5, 1.5, 6.5, 1.5, WHITE);
+        assert_eq!(lit(&c).len(), 6);
+        assert_eq!(c.pixel_at(3, 1), WHITE);
+
+        // halfway between rows the coverage is shared
+        let mut c = Canvas::new(8, 4);
+        c.draw_line_aa(1.5, 2.0, 6.5, 2.0, WHITE);
+        assert_eq!(c.pixel_at(3, 1), Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(c.pixel_at(3, 2), Colour::new(0.5, 0.5, 0.5));
+
+        // each column of a shallow line totals full coverage
+        let mut c = Canvas::new(16, 8);
+        c.draw_line_aa(0.5, 1.5, 15.5, 5.5, WHITE);
+        for x in 1..15 {
+            let total: f64 = (0..8).map(|y| c.pixel_at(x, y).red).sum();
+            assert!(f64::abs(total - 1.0) < 1.0e-9);
+        }
+
+        // off-canvas lines are clipped without panicking
+        c.draw_line_aa(-20.0, -3.0, 40.0, 30.0, WHITE);
+
+        // huge and non-finite end points neither hang nor panic
+        let mut c = Canvas::new(8, 4);
+        c.draw_line_aa(1.5, 1.5, 1.0e300, 1.5, WHITE);
+        assert_eq!(c.pixel_at(7, 1), WHITE);
+        c.draw_line_aa(0.0, 0.0, f64::INFINITY, 0.0, WHITE);
+        c.draw_line_aa(f64::NAN, 0.0, 1.0, 1.0, WHITE);
+        assert_eq!(c.pixel_at(3, 0), Colour::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rectangles() {
+        let mut c = Canvas::new(6, 5);
+        c.draw_rect(1, 1, 4, 3, WHITE);
+        assert_eq!(lit(&c).len(), 10);
+        assert_eq!(c.pixel_at(2, 2), Colour::new(0.0, 0.0, 0.0));
+
+        let mut c = Canvas::new(6, 5);
+        c.fill_rect(-2, 3, 5, 10, WHITE);
+        assert_eq!(lit(&c), vec![(0, 3), (1, 3), (2, 3), (0, 4), (1, 4), (2, 4)]);
+        c.fill_rect(10, 10, 3, 3, WHITE);
+        assert_eq!(lit(&c).len(), 6);
+
+        // sizes beyond the coordinate space are clamped
+        let mut c = Canvas::new(6, 5);
+        c.fill_rect(isize::MAX - 1, 0, usize::MAX, 2, WHITE);
+        c.draw_rect(4, isize::MAX - 1, 10, usize::MAX, WHITE);
+        assert!(lit(&c).is_empty());
+        c.fill_rect(isize::MIN, isize::MIN, usize::MAX, usize::MAX, WHITE);
+        assert_eq!(lit(&c).len(), 30);
+        let mut c = Canvas::new(6, 5);
+        c.draw_rect(-1, -1, usize::MAX, usize::MAX, WHITE);
+        assert!(lit(&c).is_empty());
+        c.draw_rect(2, 2, usize::MAX, usize::MAX, WHITE);
+        assert_eq!(lit(&c).len(), 4 + 2);
+    }
+
+    #[test]
+    fn circles_and_ellipses() {
+        let mut c = Canvas::new(21, 21);
+        c.draw_circle(10, 10, 8, WHITE);
+        for (x, y) in lit(&c) {
+            let r = f64::hypot(x as f64 - 10.0, y as f64 - 10.0);
+            assert!(f64::abs(r - 8.0) < 0.75, "({}, {}) at {}", x, y, r);
+            assert_eq!(c.pixel_at(20 - x, y), WHITE);
+            assert_eq!(c.pixel_at(y, x), WHITE);
+        }
+
+        let mut c = Canvas::new(41, 41);
+        c.fill_circle(20, 20, 15, WHITE);
+        let area = lit(&c).len() as f64;
+        // the outline pixels are inside, so the disc reaches r + 1/2
+        assert!(f64::abs(area / (std::f64::consts::PI * 15.5 * 15.5) - 1.0) < 0.02);
+
+        let mut c = Canvas::new(30, 12);
+        c.draw_ellipse(15, 6, 12, 4, WHITE);
+        assert_eq!(c.pixel_at(3, 6), WHITE);
+        assert_eq!(c.pixel_at(27, 6), WHITE);
+        assert_eq!(c.pixel_at(15, 2), WHITE);
+        assert_eq!(c.pixel_at(15, 10), WHITE);
+        let outline = lit(&c);
+        c.fill_ellipse(15, 6, 12, 4, WHITE);
+        assert!(outline.iter().all(|&(x, y)| c.pixel_at(x, y) == WHITE));
+        assert_eq!(c.pixel_at(15, 6), WHITE);
+        assert_eq!(c.pixel_at(3, 2), Colour::new(0.0, 0.0, 0.0));
+
+        // partly off the canvas
+        c.fill_circle(0, 0, 50, WHITE);
+        assert_eq!(lit(&c).len(), 30*12);
+
+        // huge radii and far away centres are clipped without overflowing
+        let mut c = Canvas::new(100, 100);
+        c.draw_circle(50, 50, 50000, WHITE);
+        assert!(lit(&c).is_empty());
+        c.draw_circle(isize::MAX - 2, 0, 5, WHITE);
+        c.fill_circle(isize::MIN, isize::MAX, isize::MAX as usize / 2, WHITE);
+        c.fill_ellipse(isize::MAX, 0, 3, usize::MAX, WHITE);
+        assert!(lit(&c).is_empty());
+        // the edge of a circle of radius 50000 crosses the canvas
+        c.draw_circle(50, 50050, 50000, WHITE);
+        assert_eq!(lit(&c).len(), 100);
+        assert!(lit(&c).iter().all(|&(_, y)| y == 50));
+        c.fill_circle(50, 50, usize::MAX, WHITE);
+        assert_eq!(lit(&c).len(), 100*100);
+    }
+
+    #[test]
+    fn triangles() {
+        let red = Colour::new(1.0, 0.0, 0.0);
+        let green = Colour::new(0.0, 1.0, 0.0);
+        let blue = Colour::new(0.0, 0.0, 1.0);
+        let mut c = Canvas::new(10, 10);
+        c.fill_triangle([(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)], [red, green, blue]);
+        assert_eq!(lit(&c).len(), 55);
+        let p = c.pixel_at(0, 0);
+        assert_eq!(p, Colour::new(0.9, 0.05, 0.05));
+        let p = c.pixel_at(4, 4);
+        assert!(f64::abs(p.red + p.green + p.blue - 1.0) < 1.0e-9);
+        assert_eq!(c.pixel_at(9, 9), Colour::new(0.0, 0.0, 0.0));
+
+        // winding does not matter, off-canvas vertices are clipped
+        let mut c = Canvas::new(10, 10);
+        c.fill_triangle([(-10.0, -10.0), (-10.0, 20.0), (20.0, -10.0)], [red; 3]);
+        assert_eq!(lit(&c).len(), 55);
+    }
+
+    #[test]
+    fn polygons() {
+        let mut c = Canvas::new(10, 10);
+        c.fill_polygon(&[(1.0, 1.0), (5.0, 1.0), (5.0, 4.0), (1.0, 4.0)], WHITE);
+        assert_eq!(lit(&c).len(), 12);
+        assert_eq!(c.pixel_at(1, 1), WHITE);
+        assert_eq!(c.pixel_at(5, 1), Colour::new(0.0, 0.0, 0.0));
+
+        // a U shape, concave with a notch at the top
+        let mut c = Canvas::new(10, 10);
+        let u = [(0.0, 0.0), (3.0, 0.0), (3.0, 5.0), (6.0, 5.0), (6.0, 0.0), (9.0, 0.0), (9.0, 8.0), (0.0, 8.0)];
+        c.fill_polygon(&u, WHITE);
+        assert_eq!(c.pixel_at(4, 2), Colour::new(0.0, 0.0, 0.0));
+        assert_eq!(c.pixel_at(1, 2), WHITE);
+        assert_eq!(c.pixel_at(4, 6), WHITE);
+        assert_eq!(lit(&c).len(), 9*8 - 3*5);
+
+        // clipped
+        let mut c = Canvas::new(4, 4);
+        c.fill_polygon(&[(-100.0, -100.0), (100.0, -100.0), (0.0, 100.0)], WHITE);
+        assert!(lit(&c).len() >= 12);
+    }
+}
@@ -0,0 +1,538 @@
+use std::io;
+use std::io::Write;
+use std::fs::File;
+use super::Canvas;
+use super::zlib;
+
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const LONG_NAMES_FLAG: u32 = 0x400;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ExrPixelType {
+    Half,
+    Float,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ExrCompression {
+    None,
+    /// Byte-oriented run-length encoding, one scanline per block.
+    Rle,
+    /// zlib, sixteen scanlines per block.
+    Zip,
+}
+
+impl ExrCompression {
+    fn code(self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Rle => 1,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines_per_block(self) -> usize {
+        match self {
+            ExrCompression::Zip => 16,
+            _ => 1,
+        }
+    }
+}
+
+struct ExrChannel {
+    name: String,
+    pixel_type: ExrPixelType,
+    values: Vec<f32>,
+}
+
+/// A scanline OpenEXR image built from named channels, so that render
+/// passes can be stored side by side: `R`, `G`, `B` for the beauty
+/// pass, then e.g. `depth.Z` or `normal.R`.
+pub struct ExrImage {
+    width: usize,
+    height: usize,
+    channels: Vec<ExrChannel>,
+}
+
+impl ExrImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {width, height, channels: Vec::new()}
+    }
+
+    /// Adds a single channel. `values` are in row order and must cover
+    /// the whole image.
+    pub fn add_channel(&mut self, name: &str, pixel_type: ExrPixelType, values: &[f64]) {
+        assert_eq!(values.len(), self.width*self.height,
+            "Channel size does not match image in ExrImage::add_channel()");
+        assert!(!name.is_empty() && !name.contains('\0'), "Invalid channel name in ExrImage::add_channel()");
+        self.channels.retain(|c| c.name != name);
+        self.channels.push(ExrChannel {
+            name: name.to_string(),
+            pixel_type,
+            values: values.iter().map(|&v| v as f32).collect(),
+        });
+    }
+
+    /// Adds the red, green and blue channels of `canvas` as `layer.R`,
+    /// `layer.G` and `layer.B`, or plain `R`, `G`, `B` if `layer` is empty.
+    /// Canvases with transparency also get `A`; the colour channels stay
+    /// premultiplied, as OpenEXR expects.
+    pub fn add_layer(&mut self, layer: &str, canvas: &Canvas, pixel_type: ExrPixelType) {
+        assert!(canvas.width == self.width && canvas.height == self.height,
+            "Canvas size does not match image in ExrImage::add_layer()");
+        let prefix = if layer.is_empty() {String::new()} else {format!("{}.", layer)};
+        let red: Vec<f64> = canvas.pixels.iter().map(|c| c.red).collect();
+        let green: Vec<f64> = canvas.pixels.iter().map(|c| c.green).collect();
+        let blue: Vec<f64> = canvas.pixels.iter().map(|c| c.blue).collect();
+        self.add_channel(&format!("{}R", prefix), pixel_type, &red);
+        self.add_channel(&format!("{}G", prefix), pixel_type, &green);
+        self.add_channel(&format!("{}B", prefix), pixel_type, &blue);
+        if !canvas.is_opaque() {
+            self.add_channel(&format!("{}A", prefix), pixel_type, &canvas.alpha);
+        }
+    }
+
+    pub fn write(&self, filename: &str, compression: ExrCompression) -> io::Result<()> {
+        let mut file = File::create(filename)?;
+        file.write_all(&self.bytes(compression))
+    }
+
+    pub fn bytes(&self, compression: ExrCompression) -> Vec<u8> {
+        // the file stores channels in alphabetical order
+        let mut channels: Vec<&ExrChannel> = self.channels.iter().collect();
+        channels.sort_by(|a, b| a.name.as_bytes().cmp(b.name.as_bytes()));
+
+        let long_names = channels.iter().any(|c| c.name.len() > 31);
+        let mut out = EXR_MAGIC.to_vec();
+        let flags = if long_names {LONG_NAMES_FLAG} else {0};
+        out.extend_from_slice(&(2 | flags).to_le_bytes());
+
+        // Header
+        let mut chlist = Vec::new();
+        for c in &channels {
+            chlist.extend_from_slice(c.name.as_bytes());
+            chlist.push(0);
+            let pixel_type: i32 = match c.pixel_type {
+                ExrPixelType::Half => 1,
+                ExrPixelType::Float => 2,
+            };
+            chlist.extend_from_slice(&pixel_type.to_le_bytes());
+            chlist.extend_from_slice(&[0, 0, 0, 0]); // pLinear, reserved
+            chlist.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+            chlist.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+        }
+        chlist.push(0);
+        let mut window = Vec::new();
+        for v in &[0, 0, self.width as i32 - 1, self.height as i32 - 1] {
+            window.extend_from_slice(&v.to_le_bytes());
+        }
+        write_attribute(&mut out, "channels", "chlist", &chlist);
+        write_attribute(&mut out, "compression", "compression", &[compression.code()]);
+        write_attribute(&mut out, "dataWindow", "box2i", &window);
+        write_attribute(&mut out, "displayWindow", "box2i", &window);
+        write_attribute(&mut out, "lineOrder", "lineOrder", &[0]); // increasing y
+        write_attribute(&mut out, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+        write_attribute(&mut out, "screenWindowCenter", "v2f", &[0; 8]);
+        write_attribute(&mut out, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+        out.push(0);
+
+        // Offset table, filled in as the blocks are written
+        let lines = compression.lines_per_block();
+        let block_count = self.height.div_ceil(lines);
+        let table_start = out.len();
+        out.resize(table_start + 8*block_count, 0);
+
+        for block in 0..block_count {
+            let y0 = block * lines;
+            let y1 = usize::min(y0 + lines, self.height);
+            let mut raw = Vec::new();
+            for y in y0..y1 {
+                for c in &channels {
+                    let row = &c.values[y*self.width..(y + 1)*self.width];
+                    match c.pixel_type {
+                        ExrPixelType::Half => {
+                            for &v in row {
+                                raw.extend_from_slice(&f32_to_half(v).to_le_bytes());
+                            }
+                        },
+                        ExrPixelType::Float => {
+                            for &v in row {
+                                raw.extend_from_slice(&v.to_le_bytes());
+                            }
+                        },
+                    }
+                }
+            }
+            let data = compress_block(&raw, compression);
+
+            let offset = out.len() as u64;
+            out[table_start + 8*block..table_start + 8*block + 8].copy_from_slice(&offset.to_le_bytes());
+            out.extend_from_slice(&(y0 as i32).to_le_bytes());
+            out.extend_from_slice(&(data.len() as i32).to_le_bytes());
+            out.extend_from_slice(&data);
+        }
+        out
+    }
+}
+
+impl Canvas {
+    pub fn to_exr(&self, filename: &str, pixel_type: ExrPixelType, compression: ExrCompression) -> io::Result<()> {
+        let mut file = File::create(filename)?;
+        file.write_all(&self.exr_bytes(pixel_type, compression))
+    }
+
+    /// The canvas as the `R`, `G` and `B` channels of an OpenEXR image.
+    pub fn exr_bytes(&self, pixel_type: ExrPixelType, compression: ExrCompression) -> Vec<u8> {
+        let mut image = ExrImage::new(self.width, self.height);
+        image.add_layer("", self, pixel_type);
+        image.bytes(compression)
+    }
+}
+
+fn write_attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+/// Converts to IEEE 754 half precision, rounding to nearest even.
+pub(crate) fn f32_to_half(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x007f_ffff;
+
+    if exp == 0xff {
+        // infinity, or NaN with the top mantissa bit kept set
+        let nan = if mant != 0 {0x0200 | (mant >> 13) as u16} else {0};
+        return sign | 0x7c00 | nan;
+    }
+    let e = exp - 127 + 15;
+    if e >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if e <= 0 {
+        if e < -10 {
+            return sign;
+        }
+        // subnormal: shift the mantissa, implicit bit included
+        let m = mant | 0x0080_0000;
+        let shift = (14 - e) as u32;
+        let mut h = m >> shift;
+        let rem = m & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        if rem > halfway || (rem == halfway && h & 1 == 1) {
+            h += 1;
+        }
+        return sign | h as u16;
+    }
+    let mut h = ((e as u32) << 10) | (mant >> 13);
+    let rem = mant & 0x1fff;
+    // a carry out of the mantissa correctly bumps the exponent
+    if rem > 0x1000 || (rem == 0x1000 && h & 1 == 1) {
+        h += 1;
+    }
+    sign | h as u16
+}
+
+/// Predictor and byte interleaving shared by the RLE and ZIP schemes,
+/// then the compressor itself. Falls back to the raw bytes when
+/// compression does not help, which readers detect from the size.
+fn compress_block(raw: &[u8], compression: ExrCompression) -> Vec<u8> {
+    if compression == ExrCompression::None {
+        return raw.to_vec();
+    }
+    let half = raw.len().div_ceil(2);
+    let mut tmp = vec![0u8; raw.len()];
+    for (i, &b) in raw.iter().enumerate() {
+        let j = if i % 2 == 0 {i / 2} else {half + i / 2};
+        tmp[j] = b;
+    }
+    let mut p = tmp.first().copied().unwrap_or(0);
+    for t in tmp.iter_mut().skip(1) {
+        let d = (*t as i32 - p as i32 + 128 + 256) as u8;
+        p = *t;
+        *t = d;
+    }
+    let packed = match compression {
+        ExrCompression::Rle => rle_compress(&tmp),
+        _ => zlib::compress(&tmp, zlib::DeflateMode::FixedHuffman),
+    };
+    if packed.len() < raw.len() {
+        packed
+    } else {
+        raw.to_vec()
+    }
+}
+
+/// OpenEXR's RLE: a non-negative count `n` is a run of `n + 1` copies
+/// of the next byte, a negative count `-n` precedes `n` literal bytes.
+fn rle_compress(data: &[u8]) -> Vec<u8> {
+    const MIN_RUN_LENGTH: usize = 3;
+    const MAX_RUN_LENGTH: usize = 127;
+    let mut out = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let mut end = start + 1;
+        while end < data.len() && data[end] == data[start] && end - start - 1 < MAX_RUN_LENGTH {
+            end += 1;
+        }
+        if end - start >= MIN_RUN_LENGTH {
+            out.push((end - start - 1) as u8);
+            out.push(data[start]);
+        } else {
+            // extend the literal until three equal bytes start a run
+            while end < data.len()
+                && (end + 1 >= data.len() || data[end] != data[end + 1]
+                    || end + 2 >= data.len() || data[end + 1] != data[end + 2])
+                && end - start < MAX_RUN_LENGTH {
+                end += 1;
+            }
+            out.push((-((end - start) as i32)) as u8);
+            out.extend_from_slice(&data[start..end]);
+        }
+        start = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{Colour,Rgba};
+
+    fn half_to_f32(h: u16) -> f32 {
+        let sign = if h & 0x8000 != 0 {-1.0} else {1.0};
+        let exp = ((h >> 10) & 0x1f) as i32;
+        let mant = (h & 0x3ff) as f32;
+        match exp {
+            0 => sign * mant * f32::powi(2.0, -24),
+            0x1f => if mant == 0.0 {sign * f32::INFINITY} else {f32::NAN},
+            _ => sign * (1.0 + mant / 1024.0) * f32::powi(2.0, exp - 15),
+        }
+    }
+
+    fn rle_decompress(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let count = data[i] as i8;
+            if count < 0 {
+                let n = (-(count as i32)) as usize;
+                out.extend_from_slice(&data[i + 1..i + 1 + n]);
+                i += 1 + n;
+            } else {
+                out.extend(std::iter::repeat_n(data[i + 1], count as usize + 1));
+                i += 2;
+            }
+        }
+        out
+    }
+
+    /// Undoes `compress_block`, knowing the expected raw size.
+    fn decompress_block(data: &[u8], compression: ExrCompression, raw_len: usize) -> Vec<u8> {
+        if data.len() == raw_len {
+            return data.to_vec();
+        }
+        let mut tmp = match compression {
+            ExrCompression::Rle => rle_decompress(data),
+            _ => zlib::decompress(data, raw_len).unwrap(),
+        };
+        for i in 1..tmp.len() {
+            tmp[i] = (tmp[i - 1] as i32 + tmp[i] as i32 - 128) as u8;
+        }
+        let half = raw_len.div_ceil(2);
+        (0..raw_len).map(|i| if i % 2 == 0 {tmp[i / 2]} else {tmp[half + i / 2]}).collect()
+    }
+
+    #[test]
+    fn half_conversion() {
+        assert_eq!(f32_to_half(0.0), 0x0000);
+        assert_eq!(f32_to_half(-0.0), 0x8000);
+        assert_eq!(f32_to_half(1.0), 0x3c00);
+        assert_eq!(f32_to_half(-2.0), 0xc000);
+        assert_eq!(f32_to_half(65504.0), 0x7bff);
+        assert_eq!(f32_to_half(65520.0), 0x7c00);
+        assert_eq!(f32_to_half(1.0e10), 0x7c00);
+        assert_eq!(f32_to_half(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_half(f32::NAN) & 0x7e00, 0x7e00);
+        assert_eq!(f32_to_half(f32::powi(2.0, -24)), 0x0001);
+        assert_eq!(f32_to_half(f32::powi(2.0, -14)), 0x0400);
+        assert_eq!(f32_to_half(1.0e-9), 0x0000);
+        // 1 + 2^-11 is halfway between two halves and rounds to even
+        assert_eq!(f32_to_half(1.0 + f32::powi(2.0, -11)), 0x3c00);
+        assert_eq!(f32_to_half(1.0 + 3.0*f32::powi(2.0, -11)), 0x3c02);
+        for &v in &[0.1f32, 0.5, 2.71, 1000.0, 6.0e-5] {
+            assert!(f32::abs(half_to_f32(f32_to_half(v)) - v) <= v * 1.0e-3);
+        }
+    }
+
+    #[test]
+    fn rle() {
+        let data = [5, 5, 5, 5, 1, 2, 3, 3, 9, 9, 9];
+        let packed = rle_compress(&data);
+        assert_eq!(packed, vec![3, 5, 0xfc, 1, 2, 3, 3, 2, 9]);
+        assert_eq!(rle_decompress(&packed), data.to_vec());
+
+        let long: Vec<u8> = (0..1000).map(|i| if i % 300 < 200 {7} else {(i % 13) as u8}).collect();
+        assert_eq!(rle_decompress(&rle_compress(&long)), long);
+    }
+
+    type Parsed = (Vec<(String, i32)>, u8, (i32, i32), Vec<Vec<f32>>);
+
+    /// Minimal reader for the files written above: header attributes
+    /// and the channel values, widened back to f32.
+    fn parse(bytes: &[u8]) -> Parsed {
+        assert_eq!(&bytes[..4], &EXR_MAGIC);
+        let mut pos = 8;
+        let cstr = |pos: &mut usize| -> String {
+            let end = bytes[*pos..].iter().position(|&b| b == 0).unwrap();
+            let s = String::from_utf8(bytes[*pos..*pos + end].to_vec()).unwrap();
+            *pos += end + 1;
+            s
+        };
+        let i32_at = |pos: usize| i32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]);
+        let mut channels = Vec::new();
+        let mut compression = 0;
+        let mut size = (0, 0);
+        loop {
+            let name = cstr(&mut pos);
+            if name.is_empty() {
+                break;
+            }
+            let _kind = cstr(&mut pos);
+            let len = i32_at(pos) as usize;
+            pos += 4;
+            match name.as_str() {
+                "channels" => {
+                    let mut p = pos;
+                    loop {
+                        let ch = cstr(&mut p);
+                        if ch.is_empty() {
+                            break;
+                        }
+                        channels.push((ch, i32_at(p)));
+                        p += 16;
+                    }
+                },
+                "compression" => compression = bytes[pos],
+                "dataWindow" => size = (i32_at(pos + 8) + 1, i32_at(pos + 12) + 1),
+                _ => {},
+            }
+            pos += len;
+        }
+
+        let mode = match compression {
+            0 => ExrCompression::None,
+            1 => ExrCompression::Rle,
+            _ => ExrCompression::Zip,
+        };
+        let (w, h) = (size.0 as usize, size.1 as usize);
+        let lines = mode.lines_per_block();
+        let blocks = h.div_ceil(lines);
+        let mut values = vec![Vec::new(); channels.len()];
+        for block in 0..blocks {
+            let p = i32_at(pos + 8*block) as usize;
+            let y0 = i32_at(p) as usize;
+            assert_eq!(y0, block * lines);
+            let len = i32_at(p + 4) as usize;
+            let nlines = usize::min(lines, h - y0);
+            let line_bytes: usize = channels.iter().map(|(_, t)| if *t == 1 {2*w} else {4*w}).sum();
+            let raw = decompress_block(&bytes[p + 8..p + 8 + len], mode, nlines*line_bytes);
+            let mut q = 0;
+            for _ in 0..nlines {
+                for (i, (_, t)) in channels.iter().enumerate() {
+                    for _ in 0..w {
+                        let v = if *t == 1 {
+                            let v = half_to_f32(u16::from_le_bytes([raw[q], raw[q + 1]]));
+                            q += 2;
+                            v
+                        } else {
+                            let v = f32::from_le_bytes([raw[q], raw[q + 1], raw[q + 2], raw[q + 3]]);
+                            q += 4;
+                            v
+                        };
+                        values[i].push(v);
+                    }
+                }
+            }
+        }
+        (channels, compression, size, values)
+    }
+
+    fn test_canvas(width: usize, height: usize) -> Canvas {
+        let mut c = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = if y % 4 == 0 {2.0} else {x as f64 * 0.25 + y as f64 * 10.0};
+                c.write_pixel(x, y, Colour::new(v, 0.5, -v));
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn exr_canvas() {
+        let c = test_canvas(9, 37);
+        for compression in &[ExrCompression::None, ExrCompression::Rle, ExrCompression::Zip] {
+            for pixel_type in &[ExrPixelType::Half, ExrPixelType::Float] {
+                let bytes = c.exr_bytes(*pixel_type, *compression);
+                let (channels, code, size, values) = parse(&bytes);
+                assert_eq!(code, compression.code());
+                assert_eq!(size, (9, 37));
+                let names: Vec<&str> = channels.iter().map(|(n, _)| n.as_str()).collect();
+                assert_eq!(names, vec!["B", "G", "R"]);
+                for (i, p) in c.pixels.iter().enumerate() {
+                    let tolerance = if *pixel_type == ExrPixelType::Half {p.red.abs() * 1.0e-3} else {0.0};
+                    assert!(f64::abs(values[2][i] as f64 - p.red) <= tolerance);
+                    assert!(f64::abs(values[1][i] as f64 - p.green) <= tolerance);
+                    assert!(f64::abs(values[0][i] as f64 - p.blue) <= tolerance);
+                }
+            }
+        }
+        let uncompressed = c.exr_bytes(ExrPixelType::Float, ExrCompression::None).len();
+        assert!(c.exr_bytes(ExrPixelType::Float, ExrCompression::Zip).len() < uncompressed);
+    }
+
+    #[test]
+    fn exr_layers() {
+        let beauty = test_canvas(4, 3);
+        let mut normals = Canvas::new(4, 3);
+        normals.write_pixel(1, 2, Colour::new(0.0, 1.0, 0.0));
+        let depth: Vec<f64> = (0..12).map(|i| i as f64 * 1.5).collect();
+
+        let mut image = ExrImage::new(4, 3);
+        image.add_layer("", &beauty, ExrPixelType::Half);
+        image.add_layer("normal", &normals, ExrPixelType::Float);
+        image.add_channel("depth.Z", ExrPixelType::Float, &depth);
+        let (channels, _, _, values) = parse(&image.bytes(ExrCompression::Zip));
+        let names: Vec<&str> = channels.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["B", "G", "R", "depth.Z", "normal.B", "normal.G", "normal.R"]);
+        assert_eq!(channels[0].1, 1);
+        assert_eq!(channels[3].1, 2);
+        assert_eq!(values[3][7], 10.5);
+        assert_eq!(values[5][9], 1.0);
+        assert_eq!(values[5][8], 0.0);
+    }
+
+    #[test]
+    fn exr_alpha() {
+        let mut c = test_canvas(3, 2);
+        c.write_rgba(1, 0, Rgba::new(0.25, 0.5, 0.0, 0.5));
+        let (channels, _, _, values) = parse(&c.exr_bytes(ExrPixelType::Half, ExrCompression::None));
+        let names: Vec<&str> = channels.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["A", "B", "G", "R"]);
+        assert_eq!(values[0], vec![1.0, 0.5, 1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(values[3][1], 0.25);
+    }
+
+    #[test]
+    #[should_panic]
+    fn exr_channel_size() {
+        let mut image = ExrImage::new(4, 3);
+        image.add_channel("Z", ExrPixelType::Float, &[0.0; 11]);
+    }
+}
@@ -0,0 +1,320 @@
+use super::{Canvas,Colour};
+use crate::maths;
+
+/// A convolution kernel with odd dimensions, centred on the pixel
+/// being filtered. Weights are in row order.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<f64>,
+}
+
+impl Kernel {
+    pub fn new(width: usize, height: usize, weights: Vec<f64>) -> Self {
+        assert!(width % 2 == 1 && height % 2 == 1, "Kernel sizes must be odd in Kernel::new()");
+        assert_eq!(weights.len(), width*height, "Wrong number of weights in Kernel::new()");
+        Self {width, height, weights}
+    }
+
+    /// 3x3 Laplacian sharpening: `amount` 0 leaves the image unchanged.
+    pub fn sharpen(amount: f64) -> Self {
+        let a = -amount;
+        Self::new(3, 3, vec![
+            0.0, a, 0.0,
+            a, 1.0 + 4.0*amount, a,
+            0.0, a, 0.0,
+        ])
+    }
+}
+
+/// Normalised Gaussian weights reaching out to three sigmas.
+fn gaussian_weights(sigma: f64) -> Vec<f64> {
+    if sigma <= 0.0 {
+        return vec![1.0];
+    }
+    let radius = (3.0 * sigma).ceil() as isize;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|i| f64::exp(-((i*i) as f64) / (2.0*sigma*sigma)))
+        .collect();
+    let sum: f64 = weights.iter().sum();
+    weights.into_iter().map(|w| w / sum).collect()
+}
+
+// Filters run on premultiplied red, green, blue and alpha together, so
+// transparent pixels don't bleed their colour into their neighbours.
+pub(super) type Pixel = [f64; 4];
+
+pub(super) fn add(a: Pixel, b: Pixel, weight: f64) -> Pixel {
+    [a[0] + b[0]*weight, a[1] + b[1]*weight, a[2] + b[2]*weight, a[3] + b[3]*weight]
+}
+
+impl Canvas {
+    pub(super) fn to_pixels(&self) -> Vec<Pixel> {
+        self.pixels.iter().zip(&self.alpha)
+            .map(|(c, &a)| [c.red, c.green, c.blue, a])
+            .collect()
+    }
+
+    /// A canvas of this one's size and output transform holding `data`.
+    fn with_pixels(&self, data: &[Pixel]) -> Canvas {
+        self.sized_with_pixels(self.width, self.height, data)
+    }
+
+    pub(super) fn sized_with_pixels(&self, width: usize, height: usize, data: &[Pixel]) -> Canvas {
+        let mut out = Canvas::new(width, height);
+        out.output = self.output;
+        for (i, p) in data.iter().enumerate() {
+            out.pixels[i] = Colour::new(p[0], p[1], p[2]);
+            // rounding in normalised weights shouldn't cost opacity
+            out.alpha[i] = if maths::almost_same(p[3], 1.0) {1.0} else {p[3]};
+        }
+        out
+    }
+
+    /// Sample with coordinates clamped to the edges.
+    fn clamped(&self, data: &[Pixel], x: isize, y: isize) -> Pixel {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        data[y*self.width + x]
+    }
+
+    pub fn convolve(&self, kernel: &Kernel) -> Canvas {
+        let data = self.to_pixels();
+        let (kx, ky) = ((kernel.width / 2) as isize, (kernel.height / 2) as isize);
+        let mut out = vec![[0.0; 4]; data.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = [0.0; 4];
+                for j in 0..kernel.height {
+                    for i in 0..kernel.width {
+                        let w = kernel.weights[j*kernel.width + i];
+                        let p = self.clamped(&data, x as isize + i as isize - kx, y as isize + j as isize - ky);
+                        sum = add(sum, p, w);
+                    }
+                }
+                out[y*self.width + x] = sum;
+            }
+        }
+        self.with_pixels(&out)
+    }
+
+    /// Convolves with `horizontal` along rows then `vertical` along
+    /// columns; both must have odd lengths.
+    pub fn convolve_separable(&self, horizontal: &[f64], vertical: &[f64]) -> Canvas {
+        assert!(horizontal.len() % 2 == 1 && vertical.len() % 2 == 1,
+            "Kernel sizes must be odd in Canvas::convolve_separable()");
+        let data = self.to_pixels();
+        let mut pass = vec![[0.0; 4]; data.len()];
+        let r = (horizontal.len() / 2) as isize;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = [0.0; 4];
+                for (i, &w) in horizontal.iter().enumerate() {
+                    sum = add(sum, self.clamped(&data, x as isize + i as isize - r, y as isize), w);
+                }
+                pass[y*self.width + x] = sum;
+            }
+        }
+        let mut out = vec![[0.0; 4]; data.len()];
+        let r = (vertical.len() / 2) as isize;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = [0.0; 4];
+                for (j, &w) in vertical.iter().enumerate() {
+                    sum = add(sum, self.clamped(&pass, x as isize, y as isize + j as isize - r), w);
+                }
+                out[y*self.width + x] = sum;
+            }
+        }
+        self.with_pixels(&out)
+    }
+
+    pub fn gaussian_blur(&self, sigma: f64) -> Canvas {
+        let weights = gaussian_weights(sigma);
+        self.convolve_separable(&weights, &weights)
+    }
+
+    /// Mean over a `(2*radius + 1)` pixel square.
+    pub fn box_blur(&self, radius: usize) -> Canvas {
+        let weights = vec![1.0 / (2*radius + 1) as f64; 2*radius + 1];
+        self.convolve_separable(&weights, &weights)
+    }
+
+    pub fn sharpen(&self, amount: f64) -> Canvas {
+        self.convolve(&Kernel::sharpen(amount))
+    }
+
+    /// Adds back `amount` times the detail the Gaussian blur removes,
+    /// leaving channels whose detail is within `threshold` untouched.
+    pub fn unsharp_mask(&self, sigma: f64, amount: f64, threshold: f64) -> Canvas {
+        let data = self.to_pixels();
+        let blurred = self.gaussian_blur(sigma).to_pixels();
+        let out: Vec<Pixel> = data.iter().zip(&blurred)
+            .map(|(p, b)| {
+                let mut q = *p;
+                for c in 0..3 {
+                    let detail = p[c] - b[c];
+                    if detail.abs() > threshold {
+                        q[c] += amount * detail;
+                    }
+                }
+                q
+            })
+            .collect();
+        self.with_pixels(&out)
+    }
+
+    /// Per-channel median over a `(2*radius + 1)` pixel square.
+    pub fn median(&self, radius: usize) -> Canvas {
+        let data = self.to_pixels();
+        let r = radius as isize;
+        let mut window = Vec::with_capacity((2*radius + 1).pow(2));
+        let mut out = vec![[0.0; 4]; data.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                for (c, value) in out[y*self.width + x].iter_mut().enumerate() {
+                    window.clear();
+                    for j in -r..=r {
+                        for i in -r..=r {
+                            window.push(self.clamped(&data, x as isize + i, y as isize + j)[c]);
+                        }
+                    }
+                    let mid = window.len() / 2;
+                    let (_, median, _) = window.select_nth_unstable_by(mid, |a, b| a.total_cmp(b));
+                    *value = *median;
+                }
+            }
+        }
+        self.with_pixels(&out)
+    }
+
+    /// Glow around highlights: the light above `threshold` luminance is
+    /// blurred by `sigma` and added back scaled by `intensity`. Meant
+    /// for HDR canvases, before tone mapping. Alpha is left alone.
+    pub fn bloom(&self, threshold: f64, sigma: f64, intensity: f64) -> Canvas {
+        let mut bright = Canvas::new(self.width, self.height);
+        for (i, c) in self.pixels.iter().enumerate() {
+            let l = c.luminance();
+            if l > threshold {
+                bright.pixels[i] = *c * ((l - threshold) / l);
+            }
+        }
+        let glow = bright.gaussian_blur(sigma);
+        let mut out = self.with_pixels(&self.to_pixels());
+        for (p, g) in out.pixels.iter_mut().zip(&glow.pixels) {
+            *p = *p + *g * intensity;
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maths::almost_same;
+
+    fn impulse(size: usize, value: f64) -> Canvas {
+        let mut c = Canvas::new(size, size);
+        c.write_pixel(size / 2, size / 2, Colour::new(value, value, value));
+        c
+    }
+
+    fn total(c: &Canvas) -> f64 {
+        c.as_slice().iter().map(|p| p.red).sum()
+    }
+
+    #[test]
+    fn kernels() {
+        let w = gaussian_weights(1.5);
+        assert_eq!(w.len(), 11);
+        assert!(almost_same(w.iter().sum(), 1.0));
+        assert!(almost_same(w[2], w[8]));
+        assert!(w[5] > w[4]);
+        assert_eq!(gaussian_weights(0.0), vec![1.0]);
+
+        let id = Kernel::new(3, 1, vec![0.0, 1.0, 0.0]);
+        let c = impulse(5, 1.0);
+        assert_eq!(c.convolve(&id).as_slice(), c.as_slice());
+        // shift right by one pixel
+        let shift = Kernel::new(3, 1, vec![1.0, 0.0, 0.0]);
+        assert_eq!(c.convolve(&shift).pixel_at(3, 2), Colour::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn even_kernel() {
+        Kernel::new(2, 3, vec![0.0; 6]);
+    }
+
+    #[test]
+    fn blurs() {
+        let c = impulse(9, 9.0);
+        let b = c.box_blur(1);
+        assert_eq!(b.pixel_at(4, 4), Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(b.pixel_at(3, 5), Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(b.pixel_at(2, 4), Colour::new(0.0, 0.0, 0.0));
+
+        let g = impulse(21, 1.0).gaussian_blur(2.0);
+        assert!(almost_same(total(&g), 1.0));
+        assert!(almost_same(g.pixel_at(8, 10).red, g.pixel_at(12, 10).red));
+        assert!(almost_same(g.pixel_at(10, 8).red, g.pixel_at(8, 10).red));
+
+        // a flat image stays flat, edges included, and opaque
+        let mut flat = Canvas::new(6, 4);
+        for y in 0..4 {
+            for x in 0..6 {
+                flat.write_pixel(x, y, Colour::new(0.3, 0.6, 0.9));
+            }
+        }
+        for out in &[flat.gaussian_blur(1.0), flat.box_blur(2), flat.sharpen(0.5), flat.median(1)] {
+            assert!(out.as_slice().iter().all(|&p| p == Colour::new(0.3, 0.6, 0.9)));
+            assert!(out.is_opaque());
+        }
+    }
+
+    #[test]
+    fn sharpening() {
+        let mut c = Canvas::new(4, 1);
+        c.write_pixel(2, 0, Colour::new(0.5, 0.5, 0.5));
+        c.write_pixel(3, 0, Colour::new(0.5, 0.5, 0.5));
+        let s = c.sharpen(1.0);
+        assert!(s.pixel_at(1, 0).red < 0.0);
+        assert!(s.pixel_at(2, 0).red > 0.5);
+
+        let u = c.unsharp_mask(1.0, 1.0, 0.0);
+        assert!(u.pixel_at(1, 0).red < 0.0);
+        assert!(u.pixel_at(2, 0).red > 0.5);
+        let untouched = c.unsharp_mask(1.0, 1.0, 0.5);
+        assert_eq!(untouched.as_slice(), c.as_slice());
+    }
+
+    #[test]
+    fn median_removes_specks() {
+        let mut c = Canvas::new(5, 5);
+        for y in 0..5 {
+            for x in 0..5 {
+                c.write_pixel(x, y, Colour::new(0.5, 0.5, 0.5));
+            }
+        }
+        c.write_pixel(2, 2, Colour::new(1.0, 0.0, 1.0));
+        c.write_pixel(0, 4, Colour::new(0.0, 0.0, 0.0));
+        let m = c.median(1);
+        assert!(m.as_slice().iter().all(|&p| p == Colour::new(0.5, 0.5, 0.5)));
+    }
+
+    #[test]
+    fn bloom_spreads_highlights() {
+        let mut c = impulse(15, 10.0);
+        c.write_pixel(0, 0, Colour::new(0.5, 0.5, 0.5));
+        let b = c.bloom(1.0, 1.5, 0.5);
+        assert!(b.pixel_at(9, 7).red > 0.0);
+        assert!(b.pixel_at(7, 7).red > 10.0);
+        // dim pixels only change by what glow reaches them
+        assert_eq!(b.pixel_at(0, 0), Colour::new(0.5, 0.5, 0.5));
+        assert!(almost_same(total(&b) - total(&c), 0.5 * 9.0));
+
+        let dim = impulse(5, 0.5);
+        assert_eq!(dim.bloom(1.0, 1.0, 1.0).as_slice(), dim.as_slice());
+    }
+}
@@ -0,0 +1,416 @@
+use std::io;
+use std::io::Write;
+use std::fs::File;
+use std::collections::HashMap;
+use super::{Animation,Canvas};
+
+// alpha below this becomes the transparent palette entry
+const ALPHA_CUTOFF: u8 = 128;
+const MAX_CODE: u16 = 4096;
+
+impl Animation {
+    pub fn to_gif(&self, filename: &str) -> io::Result<()> {
+        let mut file = io::BufWriter::new(File::create(filename)?);
+        self.write_gif(&mut file)?;
+        file.flush()
+    }
+
+    pub fn gif_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.write_gif(&mut out)?;
+        Ok(out)
+    }
+
+    /// A looping GIF89a. Every frame gets its own palette of up to 256
+    /// colours, chosen by median cut on the output samples; mostly
+    /// transparent pixels become transparent. Frames more than 65535
+    /// pixels across or down cannot be stored.
+    pub fn write_gif(&self, file: &mut impl Write) -> io::Result<()> {
+        let (width, height) = self.frames().first().map_or((0, 0), |f| (f.width, f.height));
+        if width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Frames too large for a GIF"));
+        }
+        let (width, height) = (width as u16, height as u16);
+        let mut out = b"GIF89a".to_vec();
+        push_u16(&mut out, width);
+        push_u16(&mut out, height);
+        // no global colour table, background 0, square pixels
+        out.extend_from_slice(&[0x00, 0, 0]);
+        // NETSCAPE2.0 extension: loop forever
+        out.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+
+        for (i, frame) in self.frames().iter().enumerate() {
+            let (palette, indices, transparent) = frame.palettise();
+
+            // Graphic Control Extension; delays are rounded to
+            // centiseconds without drifting from the frame rate
+            let time = |n: usize| (100.0 * n as f64 / self.fps() as f64).round() as usize;
+            let mut packed = 0;
+            if transparent.is_some() {
+                // restore to background, so earlier frames don't show
+                packed |= 2 << 2 | 1;
+            }
+            out.extend_from_slice(&[0x21, 0xf9, 4, packed]);
+            push_u16(&mut out, (time(i + 1) - time(i)) as u16);
+            out.push(transparent.unwrap_or(0));
+            out.push(0);
+
+            // Image Descriptor with a local colour table
+            let bits = (palette.len().max(2) as f64).log2().ceil() as u8;
+            out.push(0x2c);
+            push_u16(&mut out, 0);
+            push_u16(&mut out, 0);
+            push_u16(&mut out, width);
+            push_u16(&mut out, height);
+            out.push(0x80 | (bits - 1));
+            for i in 0..1 << bits {
+                out.extend_from_slice(palette.get(i).unwrap_or(&[0, 0, 0]));
+            }
+
+            let min_code_size = bits.max(2);
+            out.push(min_code_size);
+            for block in lzw_encode(&indices, min_code_size).chunks(255) {
+                out.push(block.len() as u8);
+                out.extend_from_slice(block);
+            }
+            out.push(0);
+            file.write_all(&out)?;
+            out.clear();
+        }
+        out.push(0x3b);
+        file.write_all(&out)
+    }
+}
+
+fn push_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+impl Canvas {
+    /// Palette, per-pixel indices and the transparent index, if any.
+    fn palettise(&self) -> (Vec<[u8; 3]>, Vec<u8>, Option<u8>) {
+        let samples: Vec<[u8; 4]> = (0..self.width*self.height)
+            .map(|i| self.quantised_rgba(i % self.width, i / self.width))
+            .collect();
+        let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+        let mut any_transparent = false;
+        for &[r, g, b, a] in &samples {
+            if a < ALPHA_CUTOFF {
+                any_transparent = true;
+            } else {
+                *counts.entry([r, g, b]).or_insert(0) += 1;
+            }
+        }
+        let limit = if any_transparent {255} else {256};
+        let mut palette = median_cut(counts.into_iter().collect(), limit);
+        let opaque = palette.len();
+        let transparent = if any_transparent {
+            palette.push([0, 0, 0]);
+            Some((palette.len() - 1) as u8)
+        } else {
+            None
+        };
+
+        let mut nearest: HashMap<[u8; 3], u8> = HashMap::new();
+        let indices = samples.iter()
+            .map(|&[r, g, b, a]| {
+                if a < ALPHA_CUTOFF {
+                    return transparent.unwrap();
+                }
+                *nearest.entry([r, g, b]).or_insert_with(|| closest(&palette[..opaque], [r, g, b]))
+            })
+            .collect();
+        (palette, indices, transparent)
+    }
+}
+
+fn closest(palette: &[[u8; 3]], c: [u8; 3]) -> u8 {
+    let distance = |p: &[u8; 3]| -> i32 {
+        p.iter().zip(&c).map(|(&a, &b)| (a as i32 - b as i32).pow(2)).sum()
+    };
+    (0..palette.len()).min_by_key(|&i| distance(&palette[i])).unwrap_or(0) as u8
+}
+
+/// Heckbert's median cut: the box of colours with the widest channel
+/// range is split at its weighted median until there are `limit` boxes,
+/// and each box contributes its weighted mean. Images with few enough
+/// colours keep them exactly.
+fn median_cut(colours: Vec<([u8; 3], u32)>, limit: usize) -> Vec<[u8; 3]> {
+    if colours.len() <= limit {
+        let mut exact: Vec<[u8; 3]> = colours.into_iter().map(|(c, _)| c).collect();
+        exact.sort_unstable();
+        return exact;
+    }
+    let widest = |b: &[([u8; 3], u32)]| -> (usize, u8) {
+        (0..3)
+            .map(|ch| {
+                let lo = b.iter().map(|(c, _)| c[ch]).min().unwrap();
+                let hi = b.iter().map(|(c, _)| c[ch]).max().unwrap();
+                (ch, hi - lo)
+            })
+            .max_by_key(|&(_, range)| range)
+            .unwrap()
+    };
+
+    let mut boxes = vec![colours];
+    while boxes.len() < limit {
+        let candidate = boxes.iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .max_by_key(|(_, b)| widest(b).1);
+        let i = match candidate {
+            Some((i, _)) => i,
+            None => break,
+        };
+        let mut b = boxes.swap_remove(i);
+        let (ch, _) = widest(&b);
+        b.sort_unstable_by_key(|(c, _)| c[ch]);
+        let total: u64 = b.iter().map(|&(_, n)| n as u64).sum();
+        let mut seen = 0;
+        let mut split = 1;
+        for (j, &(_, n)) in b.iter().enumerate() {
+            seen += n as u64;
+            if 2*seen >= total {
+                split = (j + 1).clamp(1, b.len() - 1);
+                break;
+            }
+        }
+        let rest = b.split_off(split);
+        boxes.push(b);
+        boxes.push(rest);
+    }
+
+    boxes.iter()
+        .map(|b| {
+            let total: f64 = b.iter().map(|&(_, n)| n as f64).sum();
+            let mut mean = [0.0; 3];
+            for &(c, n) in b {
+                for ch in 0..3 {
+                    mean[ch] += c[ch] as f64 * n as f64 / total;
+                }
+            }
+            [mean[0].round() as u8, mean[1].round() as u8, mean[2].round() as u8]
+        })
+        .collect()
+}
+
+/// Variable-width LZW as GIF uses it: codes packed least significant
+/// bit first, a clear code first and whenever the table fills up.
+fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut out = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    let mut emit = |code: u16, size: u8, out: &mut Vec<u8>| {
+        buffer |= (code as u32) << bits;
+        bits += size;
+        while bits >= 8 {
+            out.push(buffer as u8);
+            buffer >>= 8;
+            bits -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = min_code_size + 1;
+    let mut next = end + 1;
+    emit(clear, size, &mut out);
+    let mut codes = indices.iter();
+    if let Some(&first) = codes.next() {
+        let mut prefix = first as u16;
+        for &k in codes {
+            if let Some(&code) = table.get(&(prefix, k)) {
+                prefix = code;
+                continue;
+            }
+            emit(prefix, size, &mut out);
+            if next < MAX_CODE {
+                table.insert((prefix, k), next);
+                next += 1;
+                // the decoder widens one code later than it adds
+                if next > 1 << size && size < 12 {
+                    size += 1;
+                }
+            } else {
+                emit(clear, size, &mut out);
+                table.clear();
+                size = min_code_size + 1;
+                next = end + 1;
+            }
+            prefix = k as u16;
+        }
+        emit(prefix, size, &mut out);
+    }
+    emit(end, size, &mut out);
+    emit(0, 7, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{Colour,Rgba};
+
+    /// A straightforward GIF LZW decoder to check the encoder against.
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let end = clear + 1;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let reset = |table: &mut Vec<Vec<u8>>| {
+            table.clear();
+            table.extend((0..clear).map(|i| vec![i as u8]));
+            table.push(Vec::new());
+            table.push(Vec::new());
+        };
+        reset(&mut table);
+        let mut size = min_code_size as usize + 1;
+        let (mut pos, mut out, mut previous): (usize, Vec<u8>, Option<usize>) = (0, Vec::new(), None);
+        loop {
+            let mut code = 0;
+            for i in 0..size {
+                let bit = (data[(pos + i) / 8] >> ((pos + i) % 8)) & 1;
+                code |= (bit as usize) << i;
+            }
+            pos += size;
+            if code == clear {
+                reset(&mut table);
+                size = min_code_size as usize + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return out;
+            }
+            let entry = match (code < table.len(), previous) {
+                (true, _) => table[code].clone(),
+                (false, Some(p)) => {
+                    let mut e = table[p].clone();
+                    e.push(table[p][0]);
+                    e
+                },
+                (false, None) => panic!("Bad first code"),
+            };
+            if let Some(p) = previous {
+                if table.len() < MAX_CODE as usize {
+                    let mut e = table[p].clone();
+                    e.push(entry[0]);
+                    table.push(e);
+                }
+            }
+            if table.len() == 1 << size && size < 12 {
+                size += 1;
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trip() {
+        let mut seed = 12345u32;
+        let mut random = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        };
+        let noise: Vec<u8> = (0..20000).map(|_| random()).collect();
+        assert_eq!(lzw_decode(&lzw_encode(&noise, 8), 8), noise);
+
+        let runs: Vec<u8> = (0..50000).map(|i| ((i / 37) % 4) as u8).collect();
+        let encoded = lzw_encode(&runs, 2);
+        assert!(encoded.len() < runs.len() / 10);
+        assert_eq!(lzw_decode(&encoded, 2), runs);
+
+        assert_eq!(lzw_decode(&lzw_encode(&[], 2), 2), Vec::<u8>::new());
+        assert_eq!(lzw_decode(&lzw_encode(&[1], 2), 2), vec![1]);
+    }
+
+    #[test]
+    fn quantisation() {
+        // few colours are kept exactly
+        let few = vec![([10, 20, 30], 5), ([200, 0, 0], 1)];
+        assert_eq!(median_cut(few, 256), vec![[10, 20, 30], [200, 0, 0]]);
+
+        // a full RGB cube comes down to the limit, spread over it
+        let mut cube = Vec::new();
+        for r in 0..16 {
+            for g in 0..16 {
+                for b in 0..16 {
+                    cube.push(([r*17, g*17, b*17], 1));
+                }
+            }
+        }
+        let palette = median_cut(cube, 64);
+        assert_eq!(palette.len(), 64);
+        let worst = (0..16*16*16)
+            .map(|i| {
+                let c = [(i % 16 * 17) as u8, (i / 16 % 16 * 17) as u8, (i / 256 * 17) as u8];
+                let p = palette[closest(&palette, c) as usize];
+                p.iter().zip(&c).map(|(&a, &b)| (a as i32 - b as i32).abs()).max().unwrap()
+            })
+            .max()
+            .unwrap();
+        assert!(worst <= 40, "{}", worst);
+    }
+
+    #[test]
+    fn gif_structure() {
+        let mut a = Animation::new(30);
+        let mut frame = Canvas::new(3, 2);
+        frame.write_pixel(0, 0, Colour::new(1.0, 0.0, 0.0));
+        a.push(frame);
+        let mut frame = Canvas::new(3, 2);
+        frame.write_rgba(2, 1, Rgba::transparent());
+        a.push(frame);
+        let bytes = a.gif_bytes().unwrap();
+
+        assert_eq!(&bytes[..6], b"GIF89a");
+        assert_eq!(&bytes[6..10], &[3, 0, 2, 0]);
+        assert_eq!(*bytes.last().unwrap(), 0x3b);
+
+        // walk the blocks, decoding each frame
+        let mut pos = 13 + 19;
+        let mut frames = Vec::new();
+        let mut delays = Vec::new();
+        while bytes[pos] != 0x3b {
+            assert_eq!(&bytes[pos..pos + 3], &[0x21, 0xf9, 4]);
+            let packed = bytes[pos + 3];
+            delays.push(u16::from_le_bytes([bytes[pos + 4], bytes[pos + 5]]));
+            let transparent = if packed & 1 == 1 {Some(bytes[pos + 6])} else {None};
+            pos += 8;
+            assert_eq!(bytes[pos], 0x2c);
+            let table = 3 << ((bytes[pos + 9] & 7) + 1);
+            let palette = &bytes[pos + 10..pos + 10 + table];
+            pos += 10 + table;
+            let min_code_size = bytes[pos];
+            pos += 1;
+            let mut data = Vec::new();
+            while bytes[pos] != 0 {
+                let n = bytes[pos] as usize;
+                data.extend_from_slice(&bytes[pos + 1..pos + 1 + n]);
+                pos += 1 + n;
+            }
+            pos += 1;
+            let pixels: Vec<Option<[u8; 3]>> = lzw_decode(&data, min_code_size).iter()
+                .map(|&i| if Some(i) == transparent {None} else {
+                    let i = i as usize * 3;
+                    Some([palette[i], palette[i + 1], palette[i + 2]])
+                })
+                .collect();
+            frames.push(pixels);
+        }
+        // 30 fps alternates 3 and 4 centiseconds
+        assert_eq!(delays, vec![3, 4]);
+        assert_eq!(frames[0][0], Some([255, 0, 0]));
+        assert_eq!(frames[0][1], Some([0, 0, 0]));
+        assert_eq!(frames[1][4], Some([0, 0, 0]));
+        assert_eq!(frames[1][5], None);
+    }
+
+    #[test]
+    fn gif_too_large() {
+        let mut a = Animation::new(10);
+        a.push(Canvas::new(65536, 1));
+        assert_eq!(a.gif_bytes().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
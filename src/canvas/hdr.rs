@@ -0,0 +1,482 @@
+//! High dynamic range formats. Both keep values above 1.0 and ignore
+//! the canvas' output transform; negative values cannot be stored in
+//! RGBE and are written as zero.
+
+use std::io;
+use std::io::{Read,Write};
+use std::fs::File;
+use super::{Canvas,Colour};
+
+// New-style run-length encoding only applies to these widths
+const MIN_RLE_WIDTH: usize = 8;
+const MAX_RLE_WIDTH: usize = 0x7fff;
+const MIN_RUN: usize = 4;
+// new-style runs of 127 pixels take two bytes in each of four channels
+const MAX_PIXELS_PER_BYTE: usize = 16;
+
+impl Canvas {
+    pub fn to_hdr(&self, filename: &str) -> io::Result<()> {
+        let mut file = File::create(filename)?;
+        file.write_all(&self.hdr_bytes())
+    }
+
+    /// Radiance RGBE with run-length encoded scanlines.
+    pub fn hdr_bytes(&self) -> Vec<u8> {
+        let mut out = format!(
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", self.height, self.width)
+            .into_bytes();
+        let mut scanline = Vec::with_capacity(self.width);
+        for y in 0..self.height {
+            scanline.clear();
+            scanline.extend((0..self.width).map(|x| to_rgbe(self.pixel_at(x, y))));
+            if (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&self.width) {
+                out.extend_from_slice(&[2, 2, (self.width >> 8) as u8, self.width as u8]);
+                for channel in 0..4 {
+                    let bytes: Vec<u8> = scanline.iter().map(|p| p[channel]).collect();
+                    rle_encode(&bytes, &mut out);
+                }
+            } else {
+                for p in &scanline {
+                    out.extend_from_slice(p);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn from_hdr(filename: &str) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(filename)?.read_to_end(&mut bytes)?;
+        Self::from_hdr_bytes(&bytes)
+    }
+
+    /// Reads RGBE files with flat, old-style or new-style RLE scanlines,
+    /// stored top-down (`-Y`) or bottom-up (`+Y`). Files claiming more
+    /// pixels than new-style runs could pack into their data are
+    /// rejected, as are old-style files compressed further than that.
+    pub fn from_hdr_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut pos = 0;
+        let mut next_line = || -> io::Result<&[u8]> {
+            let rest = bytes.get(pos..).unwrap_or(&[]);
+            let end = rest.iter().position(|&b| b == b'\n')
+                .ok_or_else(|| invalid("Truncated HDR header"))?;
+            pos += end + 1;
+            Ok(&rest[..end])
+        };
+
+        let magic = next_line()?;
+        if !magic.starts_with(b"#?") {
+            return Err(invalid("Not a Radiance HDR file"));
+        }
+        loop {
+            let line = next_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if let Some(format) = line.strip_prefix(b"FORMAT=") {
+                if format != b"32-bit_rle_rgbe" {
+                    return Err(invalid("Unsupported HDR pixel format"));
+                }
+            }
+        }
+        let resolution = std::str::from_utf8(next_line()?)
+            .map_err(|_| invalid("Invalid HDR resolution line"))?;
+        let fields: Vec<&str> = resolution.split_whitespace().collect();
+        let (flip_y, height, width) = match fields.as_slice() {
+            ["-Y", h, "+X", w] => (false, *h, *w),
+            ["+Y", h, "+X", w] => (true, *h, *w),
+            _ => return Err(invalid("Unsupported HDR orientation")),
+        };
+        let height: usize = height.parse().map_err(|_| invalid("Invalid HDR height"))?;
+        let width: usize = width.parse().map_err(|_| invalid("Invalid HDR width"))?;
+
+        // every scanline takes at least four bytes, and bound the pixel
+        // count before decoding so that runs cannot expand without limit
+        let pixels = width.checked_mul(height).ok_or_else(|| invalid("HDR image too large"))?;
+        let left = bytes.len() - pos;
+        if height > left / 4 || pixels / MAX_PIXELS_PER_BYTE > left {
+            return Err(invalid("HDR image larger than its data"));
+        }
+        let mut rgbe = Vec::new();
+        for _ in 0..height {
+            pos = read_scanline(bytes, pos, width, &mut rgbe)?;
+        }
+
+        let mut canvas = Canvas::new(width, height);
+        for (row, scanline) in rgbe.chunks(width.max(1)).enumerate() {
+            let y = if flip_y {height - 1 - row} else {row};
+            for (x, p) in scanline.iter().enumerate() {
+                canvas.write_pixel(x, y, from_rgbe(*p));
+            }
+        }
+        Ok(canvas)
+    }
+
+    pub fn to_pfm(&self, filename: &str) -> io::Result<()> {
+        let mut file = File::create(filename)?;
+        file.write_all(&self.pfm_bytes())
+    }
+
+    /// Colour Portable Float Map, little-endian, rows bottom-up.
+    pub fn pfm_bytes(&self) -> Vec<u8> {
+        let mut out = format!("PF\n{} {}\n-1.0\n", self.width, self.height).into_bytes();
+        out.reserve(self.width * self.height * 12);
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let c = self.pixel_at(x, y);
+                for v in &[c.red, c.green, c.blue] {
+                    out.extend_from_slice(&(*v as f32).to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+
+    pub fn from_pfm(filename: &str) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(filename)?.read_to_end(&mut bytes)?;
+        Self::from_pfm_bytes(&bytes)
+    }
+
+    /// Reads colour (`PF`) and greyscale (`Pf`) maps of either byte order.
+    pub fn from_pfm_bytes(bytes: &[u8]) -> io::Result<Self> {
+        // the header is three whitespace-separated tokens after the magic
+        let mut tokens = Vec::new();
+        let mut pos = 0;
+        while tokens.len() < 4 {
+            while bytes.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            let start = pos;
+            while bytes.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("Truncated PFM header"));
+            }
+            tokens.push(std::str::from_utf8(&bytes[start..pos])
+                .map_err(|_| invalid("Invalid PFM header"))?);
+        }
+        pos += 1; // single whitespace before the raster
+
+        let channels = match tokens[0] {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(invalid("Not a PFM file")),
+        };
+        let width: usize = tokens[1].parse().map_err(|_| invalid("Invalid PFM width"))?;
+        let height: usize = tokens[2].parse().map_err(|_| invalid("Invalid PFM height"))?;
+        let scale: f64 = tokens[3].parse().map_err(|_| invalid("Invalid PFM scale"))?;
+        let little_endian = scale < 0.0;
+
+        let size = width.checked_mul(height)
+            .and_then(|n| n.checked_mul(channels*4))
+            .ok_or_else(|| invalid("PFM image too large"))?;
+        let data = bytes.get(pos..).and_then(|rest| rest.get(..size))
+            .ok_or_else(|| invalid("Truncated PFM raster"))?;
+        let sample = |i: usize| -> f64 {
+            let b = [data[4*i], data[4*i + 1], data[4*i + 2], data[4*i + 3]];
+            let v = if little_endian {f32::from_le_bytes(b)} else {f32::from_be_bytes(b)};
+            v as f64
+        };
+        let mut canvas = Canvas::new(width, height);
+        for row in 0..height {
+            let y = height - 1 - row;
+            for x in 0..width {
+                let i = (row*width + x) * channels;
+                let c = if channels == 3 {
+                    Colour::new(sample(i), sample(i + 1), sample(i + 2))
+                } else {
+                    Colour::new(sample(i), sample(i), sample(i))
+                };
+                canvas.write_pixel(x, y, c);
+            }
+        }
+        Ok(canvas)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Shared-exponent encoding: the mantissas are scaled so that the
+/// largest component uses the full 8 bits.
+fn to_rgbe(c: Colour) -> [u8; 4] {
+    let r = c.red.max(0.0);
+    let g = c.green.max(0.0);
+    let b = c.blue.max(0.0);
+    let v = r.max(g).max(b);
+    if v < 1.0e-32 {
+        return [0, 0, 0, 0];
+    }
+    // frexp: v = m * 2^e with m in [0.5, 1)
+    let e = v.log2().floor() as i32 + 1;
+    let scale = 256.0 / f64::powi(2.0, e);
+    let quantise = |x: f64| (x * scale).min(255.0) as u8;
+    [quantise(r), quantise(g), quantise(b), (e + 128).clamp(0, 255) as u8]
+}
+
+fn from_rgbe(p: [u8; 4]) -> Colour {
+    if p[3] == 0 {
+        return Colour::new(0.0, 0.0, 0.0);
+    }
+    let f = f64::powi(2.0, p[3] as i32 - (128 + 8));
+    Colour::new(p[0] as f64 * f, p[1] as f64 * f, p[2] as f64 * f)
+}
+
+/// Radiance's per-channel RLE: a count above 128 is a run of one byte
+/// repeated `count - 128` times, otherwise `count` literal bytes follow.
+fn rle_encode(data: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        // find the next run long enough to be worth encoding
+        let mut run_start = i;
+        let mut run_len = 0;
+        while run_start < data.len() {
+            run_len = 1;
+            while run_start + run_len < data.len()
+                && run_len < 127
+                && data[run_start + run_len] == data[run_start] {
+                run_len += 1;
+            }
+            if run_len >= MIN_RUN {
+                break;
+            }
+            run_start += run_len;
+        }
+        if run_len < MIN_RUN {
+            run_start = data.len();
+        }
+
+        while i < run_start {
+            let n = usize::min(128, run_start - i);
+            out.push(n as u8);
+            out.extend_from_slice(&data[i..i + n]);
+            i += n;
+        }
+        if run_start < data.len() {
+            out.push(128 + run_len as u8);
+            out.push(data[run_start]);
+            i = run_start + run_len;
+        }
+    }
+}
+
+// consecutive old-style repeat markers widen the count up to 32 bits
+const MAX_REPEAT_SHIFT: usize = 24;
+
+/// Decodes one scanline of `width` pixels starting at `pos` onto the end
+/// of `pixels`, returning the position after it.
+fn read_scanline(bytes: &[u8], mut pos: usize, width: usize, pixels: &mut Vec<[u8; 4]>) -> io::Result<usize> {
+    let byte = |pos: &mut usize| -> io::Result<u8> {
+        let b = *bytes.get(*pos).ok_or_else(|| invalid("Truncated HDR scanline"))?;
+        *pos += 1;
+        Ok(b)
+    };
+
+    let new_rle = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width)
+        && bytes.get(pos..pos + 2) == Some(&[2, 2])
+        && bytes.get(pos + 2).is_some_and(|b| b & 0x80 == 0);
+    if new_rle {
+        let encoded_width = (bytes[pos + 2] as usize) << 8 | *bytes.get(pos + 3).unwrap_or(&0) as usize;
+        if encoded_width != width {
+            return Err(invalid("HDR scanline width mismatch"));
+        }
+        pos += 4;
+        let start = pixels.len();
+        pixels.resize(start + width, [0; 4]);
+        let scanline = &mut pixels[start..];
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = byte(&mut pos)? as usize;
+                if count > 128 {
+                    let n = count - 128;
+                    let v = byte(&mut pos)?;
+                    if x + n > width {
+                        return Err(invalid("HDR run overruns scanline"));
+                    }
+                    for p in &mut scanline[x..x + n] {
+                        p[channel] = v;
+                    }
+                    x += n;
+                } else {
+                    if count == 0 || x + count > width {
+                        return Err(invalid("Bad HDR literal count"));
+                    }
+                    for p in &mut scanline[x..x + count] {
+                        p[channel] = byte(&mut pos)?;
+                    }
+                    x += count;
+                }
+            }
+        }
+        return Ok(pos);
+    }
+
+    // Flat pixels, where (1, 1, 1, n) repeats the previous pixel; the
+    // repeat count grows by 8 bits with each consecutive repeat marker
+    let mut x = 0;
+    let mut shift = 0;
+    while x < width {
+        let p = [byte(&mut pos)?, byte(&mut pos)?, byte(&mut pos)?, byte(&mut pos)?];
+        if p[0] == 1 && p[1] == 1 && p[2] == 1 {
+            if x == 0 {
+                return Err(invalid("HDR repeat with no previous pixel"));
+            }
+            if p[3] == 0 || shift > MAX_REPEAT_SHIFT {
+                return Err(invalid("Bad HDR repeat count"));
+            }
+            let n = (p[3] as usize) << shift;
+            if n > width - x {
+                return Err(invalid("HDR run overruns scanline"));
+            }
+            let prev = pixels[pixels.len() - 1];
+            pixels.extend(std::iter::repeat_n(prev, n));
+            x += n;
+            shift += 8;
+        } else {
+            pixels.push(p);
+            x += 1;
+            shift = 0;
+        }
+    }
+    Ok(pos)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hdr_canvas(width: usize, height: usize) -> Canvas {
+        let mut c = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                // flat areas to exercise runs, plus bright and dim values
+                let v = if x < width/2 {4.0} else {x as f64 * 0.37 + y as f64};
+                c.write_pixel(x, y, Colour::new(v, 0.001 * v, 123.0));
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn rgbe() {
+        assert_eq!(to_rgbe(Colour::new(0.0, 0.0, 0.0)), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe(Colour::new(1.0, 0.5, 0.25)), [128, 64, 32, 129]);
+        assert_eq!(from_rgbe([128, 64, 32, 129]), Colour::new(1.0, 0.5, 0.25));
+        assert_eq!(to_rgbe(Colour::new(-1.0, 0.0, 1.0)), [0, 0, 128, 129]);
+        let c = from_rgbe(to_rgbe(Colour::new(1000.0, 3.0, 0.1)));
+        assert!(f64::abs(c.red - 1000.0) / 1000.0 < 1.0/128.0);
+    }
+
+    #[test]
+    fn rle() {
+        let data = [1, 2, 3, 3, 3, 3, 3, 4, 5, 5, 5];
+        let mut out = Vec::new();
+        rle_encode(&data, &mut out);
+        assert_eq!(out, vec![2, 1, 2, 128 + 5, 3, 4, 4, 5, 5, 5]);
+
+        let long = vec![9u8; 300];
+        out.clear();
+        rle_encode(&long, &mut out);
+        assert_eq!(out, vec![128 + 127, 9, 128 + 127, 9, 128 + 46, 9]);
+    }
+
+    #[test]
+    fn hdr_round_trip() {
+        for &(w, h) in &[(40, 5), (3, 2)] {
+            let c = hdr_canvas(w, h);
+            let bytes = c.hdr_bytes();
+            assert!(bytes.starts_with(b"#?RADIANCE\n"));
+            let back = Canvas::from_hdr_bytes(&bytes).unwrap();
+            assert_eq!((back.width, back.height), (w, h));
+            for y in 0..h {
+                for x in 0..w {
+                    let (a, b) = (c.pixel_at(x, y), back.pixel_at(x, y));
+                    let max = a.red.max(a.green).max(a.blue);
+                    // each channel is good to within one step of the shared exponent
+                    assert!(f64::abs(a.red - b.red) <= max / 128.0);
+                    assert!(f64::abs(a.green - b.green) <= max / 128.0);
+                    assert!(f64::abs(a.blue - b.blue) <= max / 128.0);
+                }
+            }
+        }
+        // runs should make the flat half much smaller than 4 bytes per pixel
+        assert!(hdr_canvas(200, 10).hdr_bytes().len() < 200 * 10 * 3);
+    }
+
+    #[test]
+    fn hdr_old_style_and_orientation() {
+        let mut bytes = b"#?RGBE\n\n+Y 2 +X 3\n".to_vec();
+        // bottom row: one pixel then a repeat of 2
+        bytes.extend_from_slice(&[128, 0, 0, 129, 1, 1, 1, 2]);
+        // top row: flat
+        bytes.extend_from_slice(&[0, 128, 0, 129, 0, 128, 0, 129, 0, 0, 128, 130]);
+        let c = Canvas::from_hdr_bytes(&bytes).unwrap();
+        assert_eq!(c.pixel_at(2, 1), Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(c.pixel_at(1, 0), Colour::new(0.0, 1.0, 0.0));
+        assert_eq!(c.pixel_at(2, 0), Colour::new(0.0, 0.0, 2.0));
+
+        assert!(Canvas::from_hdr_bytes(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0").is_err());
+        assert!(Canvas::from_hdr_bytes(b"#?RADIANCE\n\n-Y 1 +X 2\n\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn hdr_hostile_headers() {
+        // zero repeat counts never advance, which used to grow the shift
+        // past the width of usize
+        let mut bytes = b"#?RADIANCE\n\n-Y 1 +X 3\n".to_vec();
+        bytes.extend_from_slice(&[128, 0, 0, 129]);
+        for _ in 0..9 {
+            bytes.extend_from_slice(&[1, 1, 1, 0]);
+        }
+        assert!(Canvas::from_hdr_bytes(&bytes).is_err());
+
+
+        // repeats of 255, 255 << 8, 255 << 16 and 255 << 24 pixels in a
+        // few hundred bytes
+        let mut bytes = b"#?RADIANCE\n\n-Y 1 +X 100000000000\n".to_vec();
+        bytes.extend_from_slice(&[128, 0, 0, 129]);
+        for _ in 0..4 {
+            bytes.extend_from_slice(&[1, 1, 1, 255]);
+        }
+        bytes.resize(227, 0);
+        assert!(Canvas::from_hdr_bytes(&bytes).is_err_and(|e| e.kind() == io::ErrorKind::InvalidData));
+
+        // huge sizes with no data behind them
+        assert!(Canvas::from_hdr_bytes(b"#?RADIANCE\n\n-Y 1000000 +X 1000000\n\0\0\0\0").is_err());
+        assert!(Canvas::from_hdr_bytes(b"#?RADIANCE\n\n-Y 18446744073709551615 +X 2\n").is_err());
+        assert!(Canvas::from_pfm_bytes(b"PF\n1000000 1000000\n-1.0\n\0\0\0\0").is_err());
+        assert!(Canvas::from_pfm_bytes(b"PF\n18446744073709551615 2\n-1.0\n\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let c = hdr_canvas(7, 3);
+        let bytes = c.pfm_bytes();
+        assert!(bytes.starts_with(b"PF\n7 3\n-1.0\n"));
+        let back = Canvas::from_pfm_bytes(&bytes).unwrap();
+        for y in 0..3 {
+            for x in 0..7 {
+                let (a, b) = (c.pixel_at(x, y), back.pixel_at(x, y));
+                assert_eq!(a.red as f32, b.red as f32);
+                assert_eq!(a.green as f32, b.green as f32);
+                assert_eq!(a.blue as f32, b.blue as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn pfm_greyscale_big_endian() {
+        let mut bytes = b"Pf\n1 2\n1.0\n".to_vec();
+        bytes.extend_from_slice(&2.5f32.to_be_bytes());
+        bytes.extend_from_slice(&(-1.0f32).to_be_bytes());
+        let c = Canvas::from_pfm_bytes(&bytes).unwrap();
+        // rows are stored bottom-up
+        assert_eq!(c.pixel_at(0, 1), Colour::new(2.5, 2.5, 2.5));
+        assert_eq!(c.pixel_at(0, 0), Colour::new(-1.0, -1.0, -1.0));
+        assert!(Canvas::from_pfm_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use super::{Canvas,Colour,Rgba};
+
+impl Canvas {
+    /// All pixels row by row, premultiplied as stored.
+    pub fn as_slice(&self) -> &[Colour] {
+        &self.pixels
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Colour]> {
+        self.pixels.chunks_exact(self.width.max(1))
+    }
+
+    /// Mutable rows of colours; alpha is left as it is.
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [Colour]> {
+        self.pixels.chunks_exact_mut(self.width.max(1))
+    }
+
+    /// `(x, y, colour)` for every pixel, row by row.
+    pub fn enumerate_pixels(&self) -> impl Iterator<Item = (usize, usize, &Colour)> {
+        let width = self.width.max(1);
+        self.pixels.iter().enumerate().map(move |(i, c)| (i % width, i / width, c))
+    }
+
+    /// Splits the canvas into disjoint tiles of at most `tile_width` by
+    /// `tile_height` pixels, in row order. Each tile borrows only its
+    /// own pixels, so tiles can be handed to different threads.
+    pub fn tiles_mut(&mut self, tile_width: usize, tile_height: usize) -> Vec<TileMut<'_>> {
+        assert!(tile_width > 0 && tile_height > 0, "Empty tile size in Canvas::tiles_mut()");
+        let (width, height) = (self.width, self.height);
+        let across = width.div_ceil(tile_width);
+        let down = height.div_ceil(tile_height);
+        let mut tiles: Vec<TileMut<'_>> = (0..across*down)
+            .map(|i| {
+                let (x, y) = ((i % across) * tile_width, (i / across) * tile_height);
+                TileMut {
+                    x, y,
+                    width: usize::min(tile_width, width - x),
+                    height: usize::min(tile_height, height - y),
+                    pixels: Vec::new(),
+                    alpha: Vec::new(),
+                }
+            })
+            .collect();
+        if width == 0 {
+            return tiles;
+        }
+        let rows = self.pixels.chunks_exact_mut(width).zip(self.alpha.chunks_exact_mut(width));
+        for (y, (row, alpha)) in rows.enumerate() {
+            let first = (y / tile_height) * across;
+            let pieces = row.chunks_mut(tile_width).zip(alpha.chunks_mut(tile_width));
+            for (tile, (pixels, alpha)) in tiles[first..first + across].iter_mut().zip(pieces) {
+                tile.pixels.push(pixels);
+                tile.alpha.push(alpha);
+            }
+        }
+        tiles
+    }
+}
+
+/// A mutable rectangle of a canvas from `Canvas::tiles_mut`. Pixel
+/// coordinates are relative to the tile's top left corner, which sits
+/// at `(x(), y())` on the canvas.
+pub struct TileMut<'a> {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    pixels: Vec<&'a mut [Colour]>,
+    alpha: Vec<&'a mut [f64]>,
+}
+
+impl TileMut<'_> {
+    pub fn x(&self) -> usize {
+        self.x
+    }
+
+    pub fn y(&self) -> usize {
+        self.y
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel_at(&self, x: usize, y: usize) -> Colour {
+        self.pixels[y][x]
+    }
+
+    /// Writes an opaque pixel, as `Canvas::write_pixel`.
+    pub fn write_pixel(&mut self, x: usize, y: usize, c: Colour) {
+        self.pixels[y][x] = c;
+        self.alpha[y][x] = 1.0;
+    }
+
+    pub fn write_rgba(&mut self, x: usize, y: usize, c: Rgba) {
+        self.pixels[y][x] = c.colour();
+        self.alpha[y][x] = c.alpha;
+    }
+
+    /// Row `y` of the tile; alpha is left as it is.
+    pub fn row_mut(&mut self, y: usize) -> &mut [Colour] {
+        self.pixels[y]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn numbered(width: usize, height: usize) -> Canvas {
+        let mut c = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                c.write_pixel(x, y, Colour::new(x as f64, y as f64, 0.0));
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn rows_and_pixels() {
+        let mut c = numbered(3, 2);
+        assert_eq!(c.as_slice().len(), 6);
+        assert_eq!(c.as_slice()[4], Colour::new(1.0, 1.0, 0.0));
+        let rows: Vec<&[Colour]> = c.rows().collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1][2], Colour::new(2.0, 1.0, 0.0));
+
+        for (x, y, p) in c.enumerate_pixels() {
+            assert_eq!(*p, Colour::new(x as f64, y as f64, 0.0));
+        }
+        assert_eq!(c.enumerate_pixels().count(), 6);
+
+        for row in c.rows_mut() {
+            row[0] = Colour::new(9.0, 9.0, 9.0);
+        }
+        assert_eq!(c.pixel_at(0, 1), Colour::new(9.0, 9.0, 9.0));
+        assert_eq!(c.pixel_at(1, 1), Colour::new(1.0, 1.0, 0.0));
+
+        let mut empty = Canvas::new(0, 5);
+        assert_eq!(empty.rows().count(), 0);
+        assert_eq!(empty.rows_mut().count(), 0);
+        assert!(empty.tiles_mut(4, 4).iter().all(|t| t.width() == 0));
+    }
+
+    #[test]
+    fn tiles_cover_canvas() {
+        let mut c = numbered(10, 7);
+        let tiles = c.tiles_mut(4, 3);
+        assert_eq!(tiles.len(), 3*3);
+        let mut covered = 0;
+        for tile in &tiles {
+            assert!(tile.width() <= 4 && tile.height() <= 3);
+            for y in 0..tile.height() {
+                for x in 0..tile.width() {
+                    let p = tile.pixel_at(x, y);
+                    assert_eq!(p, Colour::new((tile.x() + x) as f64, (tile.y() + y) as f64, 0.0));
+                    covered += 1;
+                }
+            }
+        }
+        assert_eq!(covered, 70);
+        assert_eq!((tiles[8].x(), tiles[8].y(), tiles[8].width(), tiles[8].height()), (8, 6, 2, 1));
+
+        let mut tiles = tiles;
+        tiles[4].row_mut(1)[2] = Colour::new(0.5, 0.5, 0.5);
+        drop(tiles);
+        assert_eq!(c.pixel_at(6, 4), Colour::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn tiles_across_threads() {
+        let mut c = Canvas::new(37, 23);
+        std::thread::scope(|scope| {
+            for mut tile in c.tiles_mut(8, 8) {
+                scope.spawn(move || {
+                    for y in 0..tile.height() {
+                        for x in 0..tile.width() {
+                            let (cx, cy) = (tile.x() + x, tile.y() + y);
+                            if (cx + cy) % 2 == 0 {
+                                tile.write_pixel(x, y, Colour::new(cx as f64, cy as f64, 1.0));
+                            } else {
+                                tile.write_rgba(x, y, Rgba::transparent());
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        for y in 0..23 {
+            for x in 0..37 {
+                if (x + y) % 2 == 0 {
+                    assert_eq!(c.rgba_at(x, y), Rgba::new(x as f64, y as f64, 1.0, 1.0));
+                } else {
+                    assert_eq!(c.rgba_at(x, y), Rgba::transparent());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::OnceLock;
+use super::{Canvas,Colour};
+use super::colour_space::srgb_encode;
+
+/// Transfer function applied to linear values before quantisation.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Encoding {
+    /// Values are written as they are.
+    Linear,
+    /// The sRGB opto-electronic transfer function (IEC 61966-2-1).
+    Srgb,
+    /// Plain power law, `v^(1/gamma)`.
+    Gamma(f64),
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Dither {
+    None,
+    /// 8x8 Bayer matrix.
+    Ordered,
+    /// 64x64 blue noise mask, generated once with void-and-cluster.
+    BlueNoise,
+}
+
+/// How `Colour` values become 8-bit samples in every image writer:
+/// clamp to [0, 1], encode, scale, dither, round.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct OutputTransform {
+    pub encoding: Encoding,
+    pub dither: Dither,
+}
+
+impl Default for OutputTransform {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Linear,
+            dither: Dither::None,
+        }
+    }
+}
+
+impl OutputTransform {
+    pub fn new(encoding: Encoding, dither: Dither) -> Self {
+        Self {encoding, dither}
+    }
+
+    pub fn encode(&self, v: f64) -> f64 {
+        let v = v.clamp(0.0, 1.0);
+        match self.encoding {
+            Encoding::Linear => v,
+            Encoding::Srgb => srgb_encode(v),
+            Encoding::Gamma(gamma) => v.powf(1.0/gamma),
+        }
+    }
+
+    /// Dither offset for pixel `(x, y)`, in units of one output step
+    /// and lying in [-0.5, 0.5).
+    fn threshold(&self, x: usize, y: usize) -> f64 {
+        match self.dither {
+            Dither::None => 0.0,
+            Dither::Ordered => (BAYER_8X8[y % 8][x % 8] as f64 + 0.5) / 64.0 - 0.5,
+            Dither::BlueNoise => {
+                let size = BLUE_NOISE_SIZE;
+                let rank = blue_noise()[(y % size)*size + x % size];
+                (rank as f64 + 0.5) / (size*size) as f64 - 0.5
+            },
+        }
+    }
+
+    pub fn quantise_channel(&self, v: f64, x: usize, y: usize) -> u8 {
+        let scaled = self.encode(v) * 255.0 + self.threshold(x, y);
+        scaled.round().clamp(0.0, 255.0) as u8
+    }
+
+    /// Red, green and blue samples of `c` when it sits at pixel `(x, y)`.
+    pub fn quantise(&self, c: Colour, x: usize, y: usize) -> [u8; 3] {
+        [
+            self.quantise_channel(c.red, x, y),
+            self.quantise_channel(c.green, x, y),
+            self.quantise_channel(c.blue, x, y),
+        ]
+    }
+}
+
+impl Canvas {
+    pub fn output_transform(&self) -> OutputTransform {
+        self.output
+    }
+
+    /// Sets the transform used by all the image writers.
+    pub fn set_output_transform(&mut self, output: OutputTransform) {
+        self.output = output;
+    }
+
+    /// Output samples of pixel `(x, y)` under the canvas' output transform.
+    /// Transparent pixels come out as if over black.
+    pub fn quantised_pixel(&self, x: usize, y: usize) -> [u8; 3] {
+        self.output.quantise(self.pixel_at(x, y), x, y)
+    }
+}
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [ 0, 32,  8, 40,  2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44,  4, 36, 14, 46,  6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [ 3, 35, 11, 43,  1, 33,  9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47,  7, 39, 13, 45,  5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+fn blue_noise() -> &'static [u16] {
+    static MASK: OnceLock<Vec<u16>> = OnceLock::new();
+    MASK.get_or_init(|| void_and_cluster(BLUE_NOISE_SIZE))
+}
+
+/// Ulichney's void-and-cluster method: returns the rank of each pixel
+/// of a `size` by `size` toroidal mask.
+fn void_and_cluster(size: usize) -> Vec<u16> {
+    let n = size * size;
+    let sigma = 1.5;
+    let radius = 6_i64;
+
+    // Gaussian splat, kept as a list of offsets for incremental updates
+    let mut kernel = Vec::new();
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            let w = f64::exp(-((dx*dx + dy*dy) as f64) / (2.0*sigma*sigma));
+            kernel.push((dx, dy, w));
+        }
+    }
+    let splat = |energy: &mut Vec<f64>, i: usize, sign: f64| {
+        let (x, y) = ((i % size) as i64, (i / size) as i64);
+        for &(dx, dy, w) in &kernel {
+            let xx = (x + dx).rem_euclid(size as i64) as usize;
+            let yy = (y + dy).rem_euclid(size as i64) as usize;
+            energy[yy*size + xx] += sign * w;
+        }
+    };
+    let tightest_cluster = |energy: &[f64], ones: &[bool]| -> usize {
+        (0..n).filter(|&i| ones[i]).max_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+    let largest_void = |energy: &[f64], ones: &[bool]| -> usize {
+        (0..n).filter(|&i| !ones[i]).min_by(|&a, &b| energy[a].total_cmp(&energy[b])).unwrap()
+    };
+
+    // Initial binary pattern: a tenth of the pixels from a fixed
+    // pseudo-random sequence, then relaxed until stable
+    let mut ones = vec![false; n];
+    let mut energy = vec![0.0; n];
+    let mut seed: u32 = 0x1234_5678;
+    let mut count = 0;
+    while count < n / 10 {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let i = seed as usize % n;
+        if !ones[i] {
+            ones[i] = true;
+            splat(&mut energy, i, 1.0);
+            count += 1;
+        }
+    }
+    loop {
+        let cluster = tightest_cluster(&energy, &ones);
+        ones[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        let void = largest_void(&energy, &ones);
+        ones[void] = true;
+        splat(&mut energy, void, 1.0);
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut rank = vec![0u16; n];
+
+    // Phase 1: remove the initial points, ranking them downward
+    let initial = ones.clone();
+    let initial_energy = energy.clone();
+    for r in (0..count).rev() {
+        let cluster = tightest_cluster(&energy, &ones);
+        ones[cluster] = false;
+        splat(&mut energy, cluster, -1.0);
+        rank[cluster] = r as u16;
+    }
+
+    // Phase 2 and 3: fill the voids, ranking upward
+    let mut ones = initial;
+    let mut energy = initial_energy;
+    for r in count..n {
+        let void = largest_void(&energy, &ones);
+        ones[void] = true;
+        splat(&mut energy, void, 1.0);
+        rank[void] = r as u16;
+    }
+    rank
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clamp_and_round() {
+        let t = OutputTransform::default();
+        assert_eq!(t.quantise(Colour::new(1.5, -0.2, 0.5), 0, 0), [255, 0, 128]);
+        assert_eq!(t.quantise_channel(0.4/255.0, 0, 0), 0);
+        assert_eq!(t.quantise_channel(0.6/255.0, 0, 0), 1);
+    }
+
+    #[test]
+    fn encodings() {
+        let srgb = OutputTransform::new(Encoding::Srgb, Dither::None);
+        assert_eq!(srgb.quantise_channel(0.0, 0, 0), 0);
+        assert_eq!(srgb.quantise_channel(1.0, 0, 0), 255);
+        assert_eq!(srgb.quantise_channel(0.5, 0, 0), 188);
+        assert_eq!(srgb.quantise_channel(0.001, 0, 0), 3);
+        let gamma = OutputTransform::new(Encoding::Gamma(2.2), Dither::None);
+        assert_eq!(gamma.quantise_channel(0.5, 0, 0), 186);
+    }
+
+    #[test]
+    fn dithering_preserves_mean() {
+        for dither in &[Dither::Ordered, Dither::BlueNoise] {
+            let t = OutputTransform::new(Encoding::Linear, *dither);
+            let v = 100.25 / 255.0;
+            let mut sum = 0.0;
+            let mut levels = std::collections::BTreeSet::new();
+            for y in 0..64 {
+                for x in 0..64 {
+                    let q = t.quantise_channel(v, x, y);
+                    levels.insert(q);
+                    sum += q as f64;
+                }
+            }
+            let mean = sum / 4096.0;
+            assert!(f64::abs(mean - 100.25) < 0.01, "{:?}: {}", dither, mean);
+            assert_eq!(levels.into_iter().collect::<Vec<_>>(), vec![100, 101]);
+        }
+    }
+
+    #[test]
+    fn blue_noise_mask() {
+        let mask = blue_noise();
+        let mut sorted = mask.to_vec();
+        sorted.sort_unstable();
+        assert!(sorted.iter().enumerate().all(|(i, &r)| i == r as usize));
+
+        // the darkest eighth of the mask should have no two points adjacent
+        let size = BLUE_NOISE_SIZE;
+        let dark = |x: usize, y: usize| mask[(y % size)*size + x % size] < 512;
+        for y in 0..size {
+            for x in 0..size {
+                if dark(x, y) {
+                    assert!(!dark(x + 1, y) && !dark(x, y + 1));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn canvas_output_transform() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(c.quantised_pixel(0, 0), [128, 128, 128]);
+        c.set_output_transform(OutputTransform::new(Encoding::Srgb, Dither::None));
+        assert_eq!(c.quantised_pixel(0, 0), [188, 188, 188]);
+        assert_eq!(c.output_transform().encoding, Encoding::Srgb);
+    }
+}
@@ -0,0 +1,144 @@
+use super::{Canvas,Colour,OutputTransform};
+
+/// Packed pixel layouts for framebuffers and texture uploads. Names
+/// list the components from the most significant bits of the packed
+/// word down, as in SDL and DRM; `Canvas::to_packed_bytes` stores each
+/// word little-endian, so `Argb8888` comes out as the bytes B, G, R, A.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum PixelFormat {
+    Rgba8888,
+    Bgra8888,
+    Argb8888,
+    Abgr8888,
+    /// As `Argb8888` with the top byte unused and set to 255.
+    Xrgb8888,
+    Rgb888,
+    Bgr888,
+    Rgb565,
+    Bgr565,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb565 | PixelFormat::Bgr565 => 2,
+            PixelFormat::Rgb888 | PixelFormat::Bgr888 => 3,
+            _ => 4,
+        }
+    }
+
+    pub fn has_alpha(self) -> bool {
+        matches!(self, PixelFormat::Rgba8888 | PixelFormat::Bgra8888 | PixelFormat::Argb8888 | PixelFormat::Abgr8888)
+    }
+
+    /// Packs 8-bit samples into the low `8*bytes_per_pixel()` bits of
+    /// a word. 565 formats round each sample to 5 or 6 bits.
+    pub fn pack(self, [r, g, b, a]: [u8; 4]) -> u32 {
+        let (r, g, b, a) = (r as u32, g as u32, b as u32, a as u32);
+        let narrow = |v: u32, max: u32| (v*max + 127) / 255;
+        match self {
+            PixelFormat::Rgba8888 => r << 24 | g << 16 | b << 8 | a,
+            PixelFormat::Bgra8888 => b << 24 | g << 16 | r << 8 | a,
+            PixelFormat::Argb8888 => a << 24 | r << 16 | g << 8 | b,
+            PixelFormat::Abgr8888 => a << 24 | b << 16 | g << 8 | r,
+            PixelFormat::Xrgb8888 => 0xff00_0000 | r << 16 | g << 8 | b,
+            PixelFormat::Rgb888 => r << 16 | g << 8 | b,
+            PixelFormat::Bgr888 => b << 16 | g << 8 | r,
+            PixelFormat::Rgb565 => narrow(r, 31) << 11 | narrow(g, 63) << 5 | narrow(b, 31),
+            PixelFormat::Bgr565 => narrow(b, 31) << 11 | narrow(g, 63) << 5 | narrow(r, 31),
+        }
+    }
+}
+
+impl Colour {
+    /// Packs as `0xRRGGBBAA` with an opaque alpha, clamping each channel.
+    pub fn to_rgba(&self) -> u32 {
+        let [r, g, b] = OutputTransform::default().quantise(*self, 0, 0);
+        PixelFormat::Rgba8888.pack([r, g, b, 255])
+    }
+}
+
+impl Canvas {
+    fn packed_pixel(&self, x: usize, y: usize, format: PixelFormat) -> u32 {
+        if format.has_alpha() {
+            format.pack(self.quantised_rgba(x, y))
+        } else {
+            let [r, g, b] = self.quantised_pixel(x, y);
+            format.pack([r, g, b, 255])
+        }
+    }
+
+    /// One word per pixel, row by row, through the output transform.
+    /// Alpha is straight; formats without alpha get the colour over
+    /// black.
+    pub fn to_packed(&self, format: PixelFormat) -> Vec<u32> {
+        let mut out = Vec::with_capacity(self.width*self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                out.push(self.packed_pixel(x, y, format));
+            }
+        }
+        out
+    }
+
+    /// As `to_packed`, with each word stored little-endian in
+    /// `format.bytes_per_pixel()` bytes and no row padding.
+    pub fn to_packed_bytes(&self, format: PixelFormat) -> Vec<u8> {
+        let size = format.bytes_per_pixel();
+        let mut out = Vec::with_capacity(self.width*self.height*size);
+        for word in self.to_packed(format) {
+            out.extend_from_slice(&word.to_le_bytes()[..size]);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::Rgba;
+
+    #[test]
+    fn to_rgba() {
+        assert_eq!(Colour::new(1.0, 0.0, 0.0).to_rgba(), 0xff00_00ff);
+        assert_eq!(Colour::new(0.0, 0.5, 1.0).to_rgba(), 0x0080_ffff);
+        assert_eq!(Colour::new(2.0, -1.0, 0.2).to_rgba(), 0xff00_33ff);
+    }
+
+    #[test]
+    fn packing() {
+        let p = [0x11, 0x22, 0x33, 0x44];
+        assert_eq!(PixelFormat::Rgba8888.pack(p), 0x1122_3344);
+        assert_eq!(PixelFormat::Bgra8888.pack(p), 0x3322_1144);
+        assert_eq!(PixelFormat::Argb8888.pack(p), 0x4411_2233);
+        assert_eq!(PixelFormat::Abgr8888.pack(p), 0x4433_2211);
+        assert_eq!(PixelFormat::Xrgb8888.pack(p), 0xff11_2233);
+        assert_eq!(PixelFormat::Rgb888.pack(p), 0x0011_2233);
+        assert_eq!(PixelFormat::Bgr888.pack(p), 0x0033_2211);
+        assert_eq!(PixelFormat::Rgb565.pack([255, 255, 255, 0]), 0xffff);
+        assert_eq!(PixelFormat::Rgb565.pack([255, 0, 0, 0]), 0xf800);
+        assert_eq!(PixelFormat::Bgr565.pack([255, 0, 0, 0]), 0x001f);
+        assert_eq!(PixelFormat::Rgb565.pack([0, 128, 0, 0]), 32 << 5);
+    }
+
+    #[test]
+    fn canvas_packed() {
+        let mut c = Canvas::new(2, 2);
+        c.write_pixel(0, 0, Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel(1, 0, Colour::new(0.0, 1.0, 0.0));
+        c.write_rgba(0, 1, Rgba::from_colour(Colour::new(0.0, 0.0, 1.0), 0.2));
+
+        let words = c.to_packed(PixelFormat::Argb8888);
+        assert_eq!(words, vec![0xffff_0000, 0xff00_ff00, 0x3300_00ff, 0xff00_0000]);
+        let bytes = c.to_packed_bytes(PixelFormat::Argb8888);
+        assert_eq!(&bytes[..8], &[0x00, 0x00, 0xff, 0xff, 0x00, 0xff, 0x00, 0xff]);
+
+        // no alpha: the transparent pixel is composited over black
+        let bytes = c.to_packed_bytes(PixelFormat::Rgb888);
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(&bytes[6..9], &[0x33, 0x00, 0x00]);
+        let bytes = c.to_packed_bytes(PixelFormat::Rgb565);
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(&bytes[..4], &[0x00, 0xf8, 0xe0, 0x07]);
+    }
+}
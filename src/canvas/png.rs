@@ -0,0 +1,661 @@
+use std::io;
+use std::io::{Read,Write};
+use std::fs::File;
+use super::{Canvas,Colour,Rgba,OutputTransform};
+use super::stream::{RowWriter,RowCounter};
+use super::zlib;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+const COLOUR_GREY: u8 = 0;
+const COLOUR_RGB: u8 = 2;
+const COLOUR_PALETTE: u8 = 3;
+const COLOUR_GREY_ALPHA: u8 = 4;
+const COLOUR_RGBA: u8 = 6;
+// width and height are limited to 31 bits
+const MAX_PNG_SIZE: usize = 0x7fff_ffff;
+
+// Adam7 passes: x start, y start, x step, y step
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4),
+    (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)];
+
+impl Canvas {
+    pub fn to_png(&self, filename: &str) -> io::Result<()> {
+        let mut file = File::create(filename)?;
+        file.write_all(&self.png_bytes())
+    }
+
+    /// Encodes as 8-bit RGB, or RGBA when the canvas has transparency,
+    /// picking a scanline filter per row.
+    pub fn png_bytes(&self) -> Vec<u8> {
+        let (channels, colour_type) = if self.is_opaque() {(3, COLOUR_RGB)} else {(4, COLOUR_RGBA)};
+        let row_len = self.width * channels;
+        let mut raw = Vec::with_capacity((row_len + 1) * self.height);
+        let mut prev = vec![0u8; row_len];
+        let mut row = vec![0u8; row_len];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pixel = &mut row[channels*x..channels*(x + 1)];
+                if channels == 3 {
+                    pixel.copy_from_slice(&self.quantised_pixel(x, y));
+                } else {
+                    pixel.copy_from_slice(&self.quantised_rgba(x, y));
+                }
+            }
+            let (filter, filtered) = best_filter(&row, &prev, channels);
+            raw.push(filter);
+            raw.extend(filtered);
+            std::mem::swap(&mut row, &mut prev);
+        }
+
+        let mut out = png_header(self.width, self.height, colour_type)
+            .expect("Canvas too large for PNG in Canvas::png_bytes()");
+        // noisy images can come out larger with Huffman coding
+        let idat = [zlib::DeflateMode::FixedHuffman, zlib::DeflateMode::Stored].iter()
+            .map(|mode| zlib::compress(&raw, *mode))
+            .min_by_key(|z| z.len())
+            .unwrap();
+        write_chunk(&mut out, b"IDAT", &idat);
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    pub fn from_png(filename: &str) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(filename)?.read_to_end(&mut bytes)?;
+        Self::from_png_bytes(&bytes)
+    }
+
+    /// Decodes any standard PNG: all colour types and bit depths, with
+    /// or without interlacing. Alpha is kept, premultiplied.
+    pub fn from_png_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < 8 || bytes[..8] != PNG_SIGNATURE {
+            return Err(invalid("Not a PNG file"));
+        }
+        let mut pos = 8;
+        let mut header = None;
+        let mut palette: Vec<[u8; 3]> = Vec::new();
+        let mut idat = Vec::new();
+        loop {
+            let len = read_u32_be(bytes, pos)? as usize;
+            let kind = bytes.get(pos + 4..pos + 8).ok_or_else(|| invalid("Truncated PNG chunk"))?;
+            let data = bytes.get(pos + 8..pos + 8 + len).ok_or_else(|| invalid("Truncated PNG chunk"))?;
+            let crc = read_u32_be(bytes, pos + 8 + len)?;
+            if zlib::crc32(&bytes[pos + 4..pos + 8 + len]) != crc {
+                return Err(invalid("PNG chunk CRC mismatch"));
+            }
+            pos += 12 + len;
+            match kind {
+                b"IHDR" => header = Some(PngHeader::parse(data)?),
+                b"PLTE" => palette = data.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect(),
+                b"IDAT" => idat.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {
+                    // bit 5 of the first byte marks ancillary chunks, which we may skip
+                    if kind[0] & 0x20 == 0 {
+                        return Err(invalid("Unsupported critical PNG chunk"));
+                    }
+                },
+            }
+        }
+        let header = header.ok_or_else(|| invalid("PNG has no IHDR chunk"))?;
+        if header.colour_type == COLOUR_PALETTE && palette.is_empty() {
+            return Err(invalid("Palette PNG has no PLTE chunk"));
+        }
+
+        // inflate no more than the header implies, and check the image
+        // data is all there before allocating a canvas of that size
+        let raw_size = header.raw_size().ok_or_else(|| invalid("PNG image too large"))?;
+        let raw = zlib::decompress(&idat, raw_size)?;
+        if raw.len() != raw_size {
+            return Err(invalid("PNG image data has the wrong length"));
+        }
+        let mut canvas = Canvas::new(header.width, header.height);
+        let mut offset = 0;
+        if header.interlaced {
+            for &(x0, y0, dx, dy) in &ADAM7 {
+                let (w, h) = header.pass_size(x0, y0, dx, dy);
+                if w == 0 || h == 0 {
+                    continue;
+                }
+                let rows = unfilter(&raw[offset..], &header, w, h)?;
+                offset += h * (1 + header.row_bytes(w));
+                for (j, row) in rows.iter().enumerate() {
+                    for i in 0..w {
+                        let c = header.pixel(row, i, &palette)?;
+                        canvas.write_rgba(x0 + i*dx, y0 + j*dy, c);
+                    }
+                }
+            }
+        } else {
+            let rows = unfilter(&raw, &header, header.width, header.height)?;
+            for (y, row) in rows.iter().enumerate() {
+                for x in 0..header.width {
+                    canvas.write_rgba(x, y, header.pixel(row, x, &palette)?);
+                }
+            }
+        }
+        Ok(canvas)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u32_be(bytes: &[u8], pos: usize) -> io::Result<u32> {
+    let b = bytes.get(pos..pos + 4).ok_or_else(|| invalid("Truncated PNG data"))?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// The signature and IHDR chunk of an 8-bit image, failing if it is too
+/// large for PNG.
+fn png_header(width: usize, height: usize, colour_type: u8) -> io::Result<Vec<u8>> {
+    if width > MAX_PNG_SIZE || height > MAX_PNG_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image too large for PNG"));
+    }
+    let mut out = PNG_SIGNATURE.to_vec();
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, colour_type, 0, 0, 0]);
+    write_chunk(&mut out, b"IHDR", &ihdr);
+    Ok(out)
+}
+
+// filtered rows are compressed into an IDAT chunk every 64K or so
+const IDAT_SIZE: usize = 1 << 16;
+
+/// Writes an 8-bit RGB or RGBA PNG row by row; see `RowWriter`. Rows are
+/// compressed in batches, so files come out somewhat larger than from
+/// `Canvas::png_bytes`.
+pub struct PngStream<W: Write> {
+    out: W,
+    rows: RowCounter,
+    channels: usize,
+    output: OutputTransform,
+    row: Vec<u8>,
+    prev: Vec<u8>,
+    raw: Vec<u8>,
+    zlib: Option<zlib::ZlibWriter>,
+}
+
+impl<W: Write> PngStream<W> {
+    /// Writes the header for a `width` by `height` image, with an alpha
+    /// channel if `alpha` is set; otherwise transparent pixels come out
+    /// as if over black.
+    pub fn new(mut out: W, width: usize, height: usize, alpha: bool) -> io::Result<Self> {
+        let (channels, colour_type) = if alpha {(4, COLOUR_RGBA)} else {(3, COLOUR_RGB)};
+        out.write_all(&png_header(width, height, colour_type)?)?;
+        Ok(Self {
+            out, channels,
+            rows: RowCounter::new(width, height),
+            output: OutputTransform::default(),
+            row: vec![0; channels*width],
+            prev: vec![0; channels*width],
+            raw: Vec::new(),
+            zlib: Some(zlib::ZlibWriter::new()),
+        })
+    }
+
+    pub fn with_output_transform(mut self, output: OutputTransform) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn write_idat(&mut self, last: bool) -> io::Result<()> {
+        let mut zlib = self.zlib.take().ok_or_else(|| invalid("PNG stream already finished"))?;
+        let mut idat = zlib.write(&self.raw);
+        if last {
+            idat.extend(zlib.finish());
+        } else {
+            self.zlib = Some(zlib);
+        }
+        self.raw.clear();
+        let mut chunk = Vec::with_capacity(idat.len() + 12);
+        write_chunk(&mut chunk, b"IDAT", &idat);
+        self.out.write_all(&chunk)
+    }
+}
+
+impl<W: Write> RowWriter for PngStream<W> {
+    fn width(&self) -> usize {
+        self.rows.width
+    }
+
+    fn height(&self) -> usize {
+        self.rows.height
+    }
+
+    fn write_rgba_row(&mut self, row: &[Rgba]) -> io::Result<()> {
+        let y = self.rows.next(row.len())?;
+        let channels = self.channels;
+        for (x, &c) in row.iter().enumerate() {
+            let pixel = &mut self.row[channels*x..channels*(x + 1)];
+            if channels == 3 {
+                pixel.copy_from_slice(&self.output.quantise(c.colour(), x, y));
+            } else {
+                pixel.copy_from_slice(&self.output.quantise_rgba(c, x, y));
+            }
+        }
+        let (filter, filtered) = best_filter(&self.row, &self.prev, channels);
+        self.raw.push(filter);
+        self.raw.extend(filtered);
+        std::mem::swap(&mut self.row, &mut self.prev);
+        if self.raw.len() >= IDAT_SIZE {
+            self.write_idat(false)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.rows.check_complete()?;
+        self.write_idat(true)?;
+        let mut iend = Vec::with_capacity(12);
+        write_chunk(&mut iend, b"IEND", &[]);
+        self.out.write_all(&iend)?;
+        self.out.flush()
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = zlib::crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    colour_type: u8,
+    interlaced: bool,
+}
+
+impl PngHeader {
+    fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() != 13 {
+            return Err(invalid("Bad IHDR length"));
+        }
+        let header = Self {
+            width: read_u32_be(data, 0)? as usize,
+            height: read_u32_be(data, 4)? as usize,
+            bit_depth: data[8],
+            colour_type: data[9],
+            interlaced: data[12] == 1,
+        };
+        let depth_ok = match header.colour_type {
+            COLOUR_GREY => matches!(header.bit_depth, 1 | 2 | 4 | 8 | 16),
+            COLOUR_PALETTE => matches!(header.bit_depth, 1 | 2 | 4 | 8),
+            COLOUR_RGB | COLOUR_GREY_ALPHA | COLOUR_RGBA => matches!(header.bit_depth, 8 | 16),
+            _ => return Err(invalid("Unknown PNG colour type")),
+        };
+        if !depth_ok || data[10] != 0 || data[11] != 0 || data[12] > 1 {
+            return Err(invalid("Unsupported PNG format"));
+        }
+        Ok(header)
+    }
+
+    fn channels(&self) -> usize {
+        match self.colour_type {
+            COLOUR_RGB => 3,
+            COLOUR_GREY_ALPHA => 2,
+            COLOUR_RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// Size of the reduced image of an interlace pass.
+    fn pass_size(&self, x0: usize, y0: usize, dx: usize, dy: usize) -> (usize, usize) {
+        ((self.width + dx - 1 - x0) / dx, (self.height + dy - 1 - y0) / dy)
+    }
+
+    /// Length of the filtered image data, or `None` if it would not fit
+    /// in memory.
+    fn raw_size(&self) -> Option<usize> {
+        let passes: &[_] = if self.interlaced {&ADAM7} else {&[(0, 0, 1, 1)]};
+        passes.iter().try_fold(0usize, |total, &(x0, y0, dx, dy)| {
+            let (w, h) = self.pass_size(x0, y0, dx, dy);
+            if w == 0 || h == 0 {
+                return Some(total);
+            }
+            h.checked_mul(1 + self.row_bytes(w))?.checked_add(total)
+        })
+    }
+
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    fn sample(&self, row: &[u8], index: usize) -> u32 {
+        match self.bit_depth {
+            16 => u16::from_be_bytes([row[2*index], row[2*index + 1]]) as u32,
+            8 => row[index] as u32,
+            depth => {
+                let depth = depth as usize;
+                let bit = index * depth;
+                let shift = 8 - depth - bit % 8;
+                ((row[bit / 8] >> shift) & ((1 << depth) - 1) as u8) as u32
+            },
+        }
+    }
+
+    fn pixel(&self, row: &[u8], x: usize, palette: &[[u8; 3]]) -> io::Result<Rgba> {
+        let max = ((1u32 << self.bit_depth) - 1) as f64;
+        let n = self.channels();
+        let s = |c: usize| self.sample(row, x*n + c) as f64 / max;
+        Ok(match self.colour_type {
+            COLOUR_PALETTE => {
+                let p = palette.get(self.sample(row, x) as usize)
+                    .ok_or_else(|| invalid("PNG palette index out of range"))?;
+                Rgba::new(p[0] as f64 / 255.0, p[1] as f64 / 255.0, p[2] as f64 / 255.0, 1.0)
+            },
+            COLOUR_GREY => Rgba::new(s(0), s(0), s(0), 1.0),
+            COLOUR_GREY_ALPHA => Rgba::from_colour(Colour::new(s(0), s(0), s(0)), s(1)),
+            COLOUR_RGB => Rgba::new(s(0), s(1), s(2), 1.0),
+            _ => Rgba::from_colour(Colour::new(s(0), s(1), s(2)), s(3)),
+        })
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn predictor(filter: u8, a: u8, b: u8, c: u8) -> io::Result<u8> {
+    match filter {
+        0 => Ok(0),
+        1 => Ok(a),
+        2 => Ok(b),
+        3 => Ok(((a as u16 + b as u16) / 2) as u8),
+        4 => Ok(paeth(a, b, c)),
+        _ => Err(invalid("Unknown PNG filter type")),
+    }
+}
+
+/// Applies filter type `filter` to `row`, with `prev` the unfiltered
+/// row above it and `bpp` the number of bytes per complete pixel.
+fn filter_row(row: &[u8], prev: &[u8], bpp: usize, filter: u8) -> Vec<u8> {
+    (0..row.len()).map(|i| {
+        let a = if i >= bpp {row[i - bpp]} else {0};
+        let c = if i >= bpp {prev[i - bpp]} else {0};
+        row[i].wrapping_sub(predictor(filter, a, prev[i], c).unwrap())
+    }).collect()
+}
+
+/// Tries all five filters and keeps the one with the smallest sum of
+/// absolute (signed) residuals, the heuristic suggested by the spec.
+fn best_filter(row: &[u8], prev: &[u8], bpp: usize) -> (u8, Vec<u8>) {
+    (0..5u8)
+        .map(|filter| (filter, filter_row(row, prev, bpp, filter)))
+        .min_by_key(|(_, filtered)| filtered.iter()
+            .map(|&v| (v as i8).unsigned_abs() as u64)
+            .sum::<u64>())
+        .unwrap()
+}
+
+/// Reverses the scanline filters for an image of `width` by `height`,
+/// returning the raw rows.
+fn unfilter(data: &[u8], header: &PngHeader, width: usize, height: usize) -> io::Result<Vec<Vec<u8>>> {
+    let row_len = header.row_bytes(width);
+    let bpp = usize::max(1, header.bits_per_pixel() / 8);
+    if data.len() < height * (row_len + 1) {
+        return Err(invalid("Truncated PNG image data"));
+    }
+    let mut rows: Vec<Vec<u8>> = Vec::with_capacity(height);
+    let mut prev = vec![0u8; row_len];
+    for y in 0..height {
+        let start = y * (row_len + 1);
+        let filter = data[start];
+        let mut row = data[start + 1..start + 1 + row_len].to_vec();
+        for i in 0..row_len {
+            let a = if i >= bpp {row[i - bpp]} else {0};
+            let c = if i >= bpp {prev[i - bpp]} else {0};
+            row[i] = row[i].wrapping_add(predictor(filter, a, prev[i], c)?);
+        }
+        prev.clone_from(&row);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_canvas() -> Canvas {
+        let mut c = Canvas::new(13, 7);
+        for y in 0..7 {
+            for x in 0..13 {
+                c.write_pixel(x, y, Colour::new(x as f64 / 12.0, y as f64 / 6.0, ((x + y) % 3) as f64 / 2.0));
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let c = test_canvas();
+        let png = c.png_bytes();
+        assert_eq!(&png[..8], &PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        let back = Canvas::from_png_bytes(&png).unwrap();
+        assert_eq!(back.width, 13);
+        assert_eq!(back.height, 7);
+        for y in 0..7 {
+            for x in 0..13 {
+                let expected = c.pixel_at(x, y);
+                let got = back.pixel_at(x, y);
+                assert!(f64::abs(expected.red - got.red) < 1.0/255.0);
+                assert!(f64::abs(expected.green - got.green) < 1.0/255.0);
+                assert!(f64::abs(expected.blue - got.blue) < 1.0/255.0);
+            }
+        }
+    }
+
+    #[test]
+    fn png_alpha() {
+        let mut c = test_canvas();
+        let png = c.png_bytes();
+        assert_eq!(png[25], COLOUR_RGB);
+        c.write_rgba(3, 2, Rgba::from_colour(Colour::new(0.6, 0.2, 1.0), 0.2));
+        c.write_rgba(4, 2, Rgba::transparent());
+        let png = c.png_bytes();
+        assert_eq!(png[25], COLOUR_RGBA);
+        let back = Canvas::from_png_bytes(&png).unwrap();
+        assert_eq!(back.rgba_at(3, 2), Rgba::new(0.12, 0.04, 0.2, 0.2));
+        assert_eq!(back.rgba_at(4, 2), Rgba::transparent());
+        assert_eq!(back.alpha_at(0, 0), 1.0);
+    }
+
+    #[test]
+    fn png_filters() {
+        let prev = [10, 20, 30, 40, 50, 60];
+        let row = [12, 25, 33, 47, 58, 61];
+        let header = PngHeader {width: 2, height: 2, bit_depth: 8, colour_type: COLOUR_RGB, interlaced: false};
+        for filter in 0..5u8 {
+            let mut data = vec![0];
+            data.extend_from_slice(&prev);
+            data.push(filter);
+            data.extend(filter_row(&row, &prev, 3, filter));
+            let rows = unfilter(&data, &header, 2, 2).unwrap();
+            assert_eq!(rows[1], row.to_vec());
+        }
+    }
+
+    #[test]
+    fn png_size_checked_before_allocating() {
+        // a valid tiny PNG claiming to be 65535x65535
+        let png = png_from_raw(65535, 65535, 8, COLOUR_RGBA, 0, &[], &[0, 0, 0, 0, 0]);
+        assert!(Canvas::from_png_bytes(&png).is_err());
+        let png = png_from_raw(u32::MAX, u32::MAX, 16, COLOUR_RGBA, 1, &[], &[0]);
+        assert!(Canvas::from_png_bytes(&png).is_err());
+        // trailing image data is rejected too
+        let png = png_from_raw(1, 1, 8, COLOUR_GREY, 0, &[], &[0, 7, 0]);
+        assert!(Canvas::from_png_bytes(&png).is_err());
+        // as is a DEFLATE bomb, before it inflates in full
+        let mut png = PNG_SIGNATURE.to_vec();
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&1u32.to_be_bytes());
+        ihdr.extend_from_slice(&1u32.to_be_bytes());
+        ihdr.extend_from_slice(&[8, COLOUR_GREY, 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &ihdr);
+        let bomb = zlib::compress(&vec![0; 1 << 22], zlib::DeflateMode::FixedHuffman);
+        assert!(bomb.len() * 100 < 1 << 22);
+        write_chunk(&mut png, b"IDAT", &bomb);
+        write_chunk(&mut png, b"IEND", &[]);
+        assert!(Canvas::from_png_bytes(&png).is_err());
+    }
+
+    fn png_from_raw(width: u32, height: u32, bit_depth: u8, colour_type: u8,
+        interlace: u8, extra: &[(&[u8; 4], Vec<u8>)], raw: &[u8]) -> Vec<u8> {
+        let mut out = PNG_SIGNATURE.to_vec();
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[bit_depth, colour_type, 0, 0, interlace]);
+        write_chunk(&mut out, b"IHDR", &ihdr);
+        for (kind, data) in extra {
+            write_chunk(&mut out, kind, data);
+        }
+        write_chunk(&mut out, b"IDAT", &zlib::compress(raw, zlib::DeflateMode::Stored));
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    #[test]
+    fn png_colour_types() {
+        // 2x1 greyscale, 16 bits
+        let png = png_from_raw(2, 1, 16, COLOUR_GREY, 0, &[], &[0, 0xff, 0xff, 0x00, 0x00]);
+        let c = Canvas::from_png_bytes(&png).unwrap();
+        assert_eq!(c.pixel_at(0, 0), Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(c.pixel_at(1, 0), Colour::new(0.0, 0.0, 0.0));
+
+        // 3x1 palette, 2 bits, with an ancillary chunk to skip
+        let plte = vec![255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let png = png_from_raw(3, 1, 2, COLOUR_PALETTE, 0,
+            &[(b"PLTE", plte), (b"tEXt", b"Comment\0hi".to_vec())], &[0, 0b0001_1000]);
+        let c = Canvas::from_png_bytes(&png).unwrap();
+        assert_eq!(c.pixel_at(0, 0), Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(c.pixel_at(1, 0), Colour::new(0.0, 1.0, 0.0));
+        assert_eq!(c.pixel_at(2, 0), Colour::new(0.0, 0.0, 1.0));
+
+        // 1x1 RGBA, 8 bits
+        let png = png_from_raw(1, 1, 8, COLOUR_RGBA, 0, &[], &[0, 255, 0, 255, 51]);
+        let c = Canvas::from_png_bytes(&png).unwrap();
+        assert_eq!(c.rgba_at(0, 0), Rgba::new(0.2, 0.0, 0.2, 0.2));
+
+        // 1x1 grey + alpha
+        let png = png_from_raw(1, 1, 8, COLOUR_GREY_ALPHA, 0, &[], &[0, 51, 0]);
+        let c = Canvas::from_png_bytes(&png).unwrap();
+        assert_eq!(c.rgba_at(0, 0), Rgba::transparent());
+    }
+
+    #[test]
+    fn png_interlaced() {
+        // 3x3 greyscale where each pixel holds its own index
+        let width = 3;
+        let mut raw = Vec::new();
+        for &(x0, y0, dx, dy) in &ADAM7 {
+            let w = (width + dx - 1 - x0) / dx;
+            let h = (width + dy - 1 - y0) / dy;
+            if w == 0 || h == 0 {
+                continue;
+            }
+            for j in 0..h {
+                raw.push(0);
+                for i in 0..w {
+                    raw.push(((y0 + j*dy) * width + x0 + i*dx) as u8 * 10);
+                }
+            }
+        }
+        let png = png_from_raw(3, 3, 8, COLOUR_GREY, 1, &[], &raw);
+        let c = Canvas::from_png_bytes(&png).unwrap();
+        for y in 0..3 {
+            for x in 0..3 {
+                let v = (y * 3 + x) as f64 * 10.0 / 255.0;
+                assert_eq!(c.pixel_at(x, y), Colour::new(v, v, v));
+            }
+        }
+    }
+
+    #[test]
+    fn png_corrupt() {
+        let mut png = test_canvas().png_bytes();
+        assert!(Canvas::from_png_bytes(&png[..20]).is_err());
+        png[20] ^= 0xff;
+        assert!(Canvas::from_png_bytes(&png).is_err());
+        assert!(Canvas::from_png_bytes(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn png_stream() {
+        let mut c = test_canvas();
+        c.write_rgba(3, 2, Rgba::from_colour(Colour::new(0.6, 0.2, 1.0), 0.2));
+        for &alpha in &[false, true] {
+            let mut stream = PngStream::new(Vec::new(), 13, 7, alpha).unwrap();
+            c.write_rows(&mut stream).unwrap();
+            let png = stream.into_inner();
+            assert_eq!(png[25], if alpha {COLOUR_RGBA} else {COLOUR_RGB});
+            let back = Canvas::from_png_bytes(&png).unwrap();
+            for y in 0..7 {
+                for x in 0..13 {
+                    if alpha {
+                        assert_eq!(back.quantised_rgba(x, y), c.quantised_rgba(x, y));
+                    } else {
+                        assert_eq!(back.quantised_pixel(x, y), c.quantised_pixel(x, y));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn png_stream_too_large() {
+        let err = PngStream::new(Vec::new(), 1 << 31, 1, false).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(PngStream::new(Vec::new(), 1, usize::MAX, true).is_err());
+    }
+
+    #[test]
+    fn png_stream_many_idats() {
+        // enough rows to flush several IDAT chunks along the way
+        let (width, height) = (300, 200);
+        let mut stream = PngStream::new(Vec::new(), width, height, false).unwrap();
+        for y in 0..height {
+            let row: Vec<Colour> = (0..width)
+                .map(|x| Colour::new(((x*7 + y*13) % 256) as f64 / 255.0, (x % 5) as f64 / 4.0, 0.5))
+                .collect();
+            stream.write_row(&row).unwrap();
+        }
+        stream.finish().unwrap();
+        assert!(stream.finish().is_err());
+        let png = stream.into_inner();
+        let idats = png.windows(4).filter(|w| w == b"IDAT").count();
+        assert!(idats > 1);
+        let back = Canvas::from_png_bytes(&png).unwrap();
+        assert_eq!(back.quantised_pixel(299, 199), [((299*7 + 199*13) % 256) as u8, 255, 128]);
+    }
+}
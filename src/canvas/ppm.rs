@@ -0,0 +1,305 @@
+use std::io;
+use std::io::{Read,Write};
+use std::fs::File;
+use super::{Canvas,Colour,Rgba,OutputTransform};
+use super::stream::{RowWriter,RowCounter};
+
+/// Netpbm colour variants: `Ascii` is P3, `Binary` is P6.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum PpmFormat {
+    Ascii,
+    Binary,
+}
+
+// Plain PPM lines should not be longer than 70 characters
+const PPM_LINE_LENGTH: usize = 70;
+
+impl Canvas {
+    pub fn to_ppm(&self, filename: &str, format: PpmFormat) -> io::Result<()> {
+        let mut file = File::create(filename)?;
+        file.write_all(&self.ppm_bytes(format))
+    }
+
+    pub fn ppm_bytes(&self, format: PpmFormat) -> Vec<u8> {
+        let magic = match format {
+            PpmFormat::Ascii => "P3",
+            PpmFormat::Binary => "P6",
+        };
+        let mut out = format!("{}\n{} {}\n255\n", magic, self.width, self.height).into_bytes();
+        match format {
+            PpmFormat::Ascii => {
+                for y in 0..self.height {
+                    let mut line = String::new();
+                    for x in 0..self.width {
+                        for v in &self.quantised_pixel(x, y) {
+                            let s = v.to_string();
+                            if !line.is_empty() && line.len() + 1 + s.len() > PPM_LINE_LENGTH {
+                                out.extend_from_slice(line.as_bytes());
+                                out.push(b'\n');
+                                line.clear();
+                            }
+                            if !line.is_empty() {
+                                line.push(' ');
+                            }
+                            line.push_str(&s);
+                        }
+                    }
+                    out.extend_from_slice(line.as_bytes());
+                    out.push(b'\n');
+                }
+            },
+            PpmFormat::Binary => {
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        out.extend_from_slice(&self.quantised_pixel(x, y));
+                    }
+                }
+            },
+        }
+        out
+    }
+
+    pub fn from_ppm(filename: &str) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        File::open(filename)?.read_to_end(&mut bytes)?;
+        Self::from_ppm_bytes(&bytes)
+    }
+
+    /// Parses a P3 or P6 image with any maxval up to 65535.
+    pub fn from_ppm_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut parser = PpmParser {bytes, pos: 0};
+        let format = match parser.token()? {
+            "P3" => PpmFormat::Ascii,
+            "P6" => PpmFormat::Binary,
+            _ => return Err(invalid("Not a P3 or P6 image")),
+        };
+        let width = parser.number()?;
+        let height = parser.number()?;
+        let maxval = parser.number()?;
+        if maxval == 0 || maxval > 65535 {
+            return Err(invalid("PPM maxval out of range"));
+        }
+
+        // check the raster can hold every pixel before allocating them
+        let pixels = width.checked_mul(height).ok_or_else(|| invalid("PPM image too large"))?;
+        let remaining = bytes.len() - parser.pos;
+        let sample_size = if maxval < 256 {1} else {2};
+        let fits = match format {
+            // at least a digit and a separator per sample
+            PpmFormat::Ascii => pixels <= (remaining + 1) / 6,
+            // exactly one whitespace byte separates the header from the raster
+            PpmFormat::Binary => pixels.checked_mul(3*sample_size)
+                .is_some_and(|size| size < remaining),
+        };
+        if !fits {
+            return Err(invalid("Truncated PPM raster"));
+        }
+
+        let mut canvas = Canvas::new(width, height);
+        let scale = 1.0 / maxval as f64;
+        match format {
+            PpmFormat::Ascii => {
+                for i in 0..pixels {
+                    let r = parser.number()?;
+                    let g = parser.number()?;
+                    let b = parser.number()?;
+                    canvas.pixels[i] = Colour::new(r as f64 * scale, g as f64 * scale, b as f64 * scale);
+                }
+            },
+            PpmFormat::Binary => {
+                let data = &parser.bytes[parser.pos + 1..];
+                let sample = |i: usize| -> f64 {
+                    let v = if sample_size == 1 {
+                        data[i] as u32
+                    } else {
+                        u16::from_be_bytes([data[2*i], data[2*i + 1]]) as u32
+                    };
+                    v as f64 * scale
+                };
+                for i in 0..pixels {
+                    canvas.pixels[i] = Colour::new(sample(3*i), sample(3*i + 1), sample(3*i + 2));
+                }
+            },
+        }
+        Ok(canvas)
+    }
+}
+
+/// Writes a binary (P6) PPM row by row; see `RowWriter`. Transparent
+/// pixels come out as if over black.
+pub struct PpmStream<W: Write> {
+    out: W,
+    rows: RowCounter,
+    output: OutputTransform,
+    row: Vec<u8>,
+}
+
+impl<W: Write> PpmStream<W> {
+    /// Writes the header for a `width` by `height` image.
+    pub fn new(mut out: W, width: usize, height: usize) -> io::Result<Self> {
+        write!(out, "P6\n{} {}\n255\n", width, height)?;
+        Ok(Self {
+            out,
+            rows: RowCounter::new(width, height),
+            output: OutputTransform::default(),
+            row: Vec::with_capacity(3*width),
+        })
+    }
+
+    pub fn with_output_transform(mut self, output: OutputTransform) -> Self {
+        self.output = output;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> RowWriter for PpmStream<W> {
+    fn width(&self) -> usize {
+        self.rows.width
+    }
+
+    fn height(&self) -> usize {
+        self.rows.height
+    }
+
+    fn write_rgba_row(&mut self, row: &[Rgba]) -> io::Result<()> {
+        let y = self.rows.next(row.len())?;
+        self.row.clear();
+        for (x, c) in row.iter().enumerate() {
+            self.row.extend_from_slice(&self.output.quantise(c.colour(), x, y));
+        }
+        self.out.write_all(&self.row)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.rows.check_complete()?;
+        self.out.flush()
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct PpmParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PpmParser<'a> {
+    /// Next whitespace-separated token, skipping `#` comments.
+    fn token(&mut self) -> io::Result<&'a str> {
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'#') => {
+                    while !matches!(self.bytes.get(self.pos), Some(b'\n') | Some(b'\r') | None) {
+                        self.pos += 1;
+                    }
+                },
+                Some(c) if c.is_ascii_whitespace() => self.pos += 1,
+                Some(_) => break,
+                None => return Err(invalid("Unexpected end of PPM data")),
+            }
+        }
+        let start = self.pos;
+        while matches!(self.bytes.get(self.pos), Some(c) if !c.is_ascii_whitespace() && *c != b'#') {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .map_err(|_| invalid("Invalid PPM header"))
+    }
+
+    fn number(&mut self) -> io::Result<usize> {
+        self.token()?.parse().map_err(|_| invalid("Invalid number in PPM data"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ppm_header() {
+        let c = Canvas::new(5, 3);
+        let ppm = String::from_utf8(c.ppm_bytes(PpmFormat::Ascii)).unwrap();
+        let lines: Vec<&str> = ppm.lines().collect();
+        assert_eq!(&lines[0..3], &["P3", "5 3", "255"]);
+        assert!(ppm.ends_with('\n'));
+    }
+
+    #[test]
+    fn ppm_line_wrapping() {
+        let mut c = Canvas::new(10, 2);
+        for y in 0..2 {
+            for x in 0..10 {
+                c.write_pixel(x, y, Colour::new(1.0, 0.8, 0.6));
+            }
+        }
+        let ppm = String::from_utf8(c.ppm_bytes(PpmFormat::Ascii)).unwrap();
+        let lines: Vec<&str> = ppm.lines().collect();
+        assert_eq!(lines[3], "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204");
+        assert_eq!(lines[4], "153 255 204 153 255 204 153 255 204 153 255 204 153");
+        assert_eq!(lines.len(), 7);
+        assert!(lines.iter().all(|l| l.len() <= PPM_LINE_LENGTH));
+    }
+
+    #[test]
+    fn ppm_round_trip() {
+        let mut c = Canvas::new(4, 3);
+        c.write_pixel(0, 0, Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel(3, 2, Colour::new(0.0, 1.0, 1.0));
+        c.write_pixel(1, 1, Colour::new(0.2, 0.4, 0.6));
+        for format in &[PpmFormat::Ascii, PpmFormat::Binary] {
+            let back = Canvas::from_ppm_bytes(&c.ppm_bytes(*format)).unwrap();
+            assert_eq!(back.width, 4);
+            assert_eq!(back.height, 3);
+            for y in 0..3 {
+                for x in 0..4 {
+                    let (a, b) = (c.pixel_at(x, y), back.pixel_at(x, y));
+                    assert!(f64::abs(a.red - b.red) < 1.0/255.0);
+                    assert!(f64::abs(a.green - b.green) < 1.0/255.0);
+                    assert!(f64::abs(a.blue - b.blue) < 1.0/255.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ppm_comments_and_maxval() {
+        let ppm = b"P3\n# a comment\n2 1 # trailing\n15\n15 0 0  0 15 15\n";
+        let c = Canvas::from_ppm_bytes(ppm).unwrap();
+        assert_eq!(c.pixel_at(0, 0), Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(c.pixel_at(1, 0), Colour::new(0.0, 1.0, 1.0));
+
+        let mut ppm = b"P6 1 1 65535\n".to_vec();
+        ppm.extend_from_slice(&[0xff, 0xff, 0x80, 0x00, 0x00, 0x00]);
+        let c = Canvas::from_ppm_bytes(&ppm).unwrap();
+        assert_eq!(c.pixel_at(0, 0), Colour::new(1.0, 32768.0/65535.0, 0.0));
+
+        assert!(Canvas::from_ppm_bytes(b"P5 1 1 255\n\0").is_err());
+        assert!(Canvas::from_ppm_bytes(b"P6 2 2 255\n\0\0\0").is_err());
+    }
+
+    #[test]
+    fn ppm_stream() {
+        let mut c = Canvas::new(4, 3);
+        c.write_pixel(0, 0, Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel(3, 2, Colour::new(0.2, 0.4, 0.6));
+        let mut stream = PpmStream::new(Vec::new(), 4, 3).unwrap();
+        c.write_rows(&mut stream).unwrap();
+        assert_eq!(stream.into_inner(), c.ppm_bytes(PpmFormat::Binary));
+    }
+
+    #[test]
+    fn ppm_size_checked_before_allocating() {
+        assert!(Canvas::from_ppm_bytes(b"P6 1000000 1000000 255\n\0\0\0").is_err());
+        // 2^62 pixels of 4 bytes wraps to zero
+        assert!(Canvas::from_ppm_bytes(b"P6 4611686018427387904 1 255\n").is_err());
+        assert!(Canvas::from_ppm_bytes(b"P6 18446744073709551615 2 255\n").is_err());
+        assert!(Canvas::from_ppm_bytes(b"P3 1000000 1000000 255\n0 0 0").is_err());
+        assert!(Canvas::from_ppm_bytes(b"P3 1 1 255\n0 0 0").is_ok());
+    }
+}
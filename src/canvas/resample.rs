@@ -0,0 +1,302 @@
+use std::f64::consts::PI;
+use super::Canvas;
+use super::filter::add;
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ResizeFilter {
+    Nearest,
+    /// Triangle filter; a box-like average when shrinking.
+    Bilinear,
+    /// Keys cubic with `a = -0.5` (Catmull-Rom).
+    Bicubic,
+    /// Windowed sinc over three lobes.
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    fn radius(self) -> f64 {
+        match self {
+            ResizeFilter::Nearest => 0.5,
+            ResizeFilter::Bilinear => 1.0,
+            ResizeFilter::Bicubic => 2.0,
+            ResizeFilter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(self, x: f64) -> f64 {
+        let x = x.abs();
+        match self {
+            ResizeFilter::Nearest => if x < 0.5 {1.0} else {0.0},
+            ResizeFilter::Bilinear => f64::max(1.0 - x, 0.0),
+            ResizeFilter::Bicubic => {
+                let a = -0.5;
+                if x < 1.0 {
+                    (a + 2.0)*x*x*x - (a + 3.0)*x*x + 1.0
+                } else if x < 2.0 {
+                    a*x*x*x - 5.0*a*x*x + 8.0*a*x - 4.0*a
+                } else {
+                    0.0
+                }
+            },
+            ResizeFilter::Lanczos3 => {
+                if x == 0.0 {
+                    1.0
+                } else if x < 3.0 {
+                    let px = PI * x;
+                    3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            },
+        }
+    }
+}
+
+/// For each output sample, the source indices and normalised weights
+/// that make it up. Shrinking widens the filter so every source pixel
+/// contributes.
+fn contributions(src: usize, dst: usize, filter: ResizeFilter) -> Vec<Vec<(usize, f64)>> {
+    let scale = src as f64 / dst as f64;
+    let stretch = scale.max(1.0);
+    let support = filter.radius() * stretch;
+    (0..dst)
+        .map(|i| {
+            let centre = (i as f64 + 0.5) * scale - 0.5;
+            if filter == ResizeFilter::Nearest {
+                let j = ((i as f64 + 0.5) * scale).floor() as usize;
+                return vec![(j.min(src - 1), 1.0)];
+            }
+            let first = (centre - support).floor() as isize;
+            let last = (centre + support).ceil() as isize;
+            let mut taps: Vec<(usize, f64)> = (first..=last)
+                .map(|j| {
+                    let w = filter.weight((j as f64 - centre) / stretch);
+                    (j.clamp(0, src as isize - 1) as usize, w)
+                })
+                .filter(|&(_, w)| w != 0.0)
+                .collect();
+            let sum: f64 = taps.iter().map(|&(_, w)| w).sum();
+            for tap in taps.iter_mut() {
+                tap.1 /= sum;
+            }
+            taps
+        })
+        .collect()
+}
+
+impl Canvas {
+    /// A copy of this canvas' output settings at a new size.
+    fn blank(&self, width: usize, height: usize) -> Canvas {
+        let mut out = Canvas::new(width, height);
+        out.output = self.output;
+        out
+    }
+
+    /// Builds a `width` by `height` canvas taking each pixel from the
+    /// source coordinates `source(x, y)` gives.
+    fn remap(&self, width: usize, height: usize, source: impl Fn(usize, usize) -> (usize, usize)) -> Canvas {
+        let mut out = self.blank(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = source(x, y);
+                out.write_rgba(x, y, self.rgba_at(sx, sy));
+            }
+        }
+        out
+    }
+
+    /// The part of the canvas inside the rectangle, clipped to the
+    /// canvas.
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Canvas {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        self.remap(width, height, |i, j| (x + i, y + j))
+    }
+
+    pub fn flip_horizontal(&self) -> Canvas {
+        self.remap(self.width, self.height, |x, y| (self.width - 1 - x, y))
+    }
+
+    pub fn flip_vertical(&self) -> Canvas {
+        self.remap(self.width, self.height, |x, y| (x, self.height - 1 - y))
+    }
+
+    /// Rotates a quarter turn clockwise.
+    pub fn rotate_90(&self) -> Canvas {
+        self.remap(self.height, self.width, |x, y| (y, self.height - 1 - x))
+    }
+
+    pub fn rotate_180(&self) -> Canvas {
+        self.remap(self.width, self.height, |x, y| (self.width - 1 - x, self.height - 1 - y))
+    }
+
+    /// Rotates a quarter turn anticlockwise.
+    pub fn rotate_270(&self) -> Canvas {
+        self.remap(self.height, self.width, |x, y| (self.width - 1 - y, x))
+    }
+
+    /// Separable resampling to `width` by `height`, filtering the
+    /// premultiplied colour and alpha together.
+    pub fn resize(&self, width: usize, height: usize, filter: ResizeFilter) -> Canvas {
+        if width == 0 || height == 0 || self.width == 0 || self.height == 0 {
+            return self.blank(width, height);
+        }
+        let data = self.to_pixels();
+        let across = contributions(self.width, width, filter);
+        let mut pass = vec![[0.0; 4]; width*self.height];
+        for y in 0..self.height {
+            for (x, taps) in across.iter().enumerate() {
+                pass[y*width + x] = taps.iter()
+                    .fold([0.0; 4], |sum, &(sx, w)| add(sum, data[y*self.width + sx], w));
+            }
+        }
+        let down = contributions(self.height, height, filter);
+        let mut out = vec![[0.0; 4]; width*height];
+        for (y, taps) in down.iter().enumerate() {
+            for x in 0..width {
+                out[y*width + x] = taps.iter()
+                    .fold([0.0; 4], |sum, &(sy, w)| add(sum, pass[sy*width + x], w));
+            }
+        }
+        self.sized_with_pixels(width, height, &out)
+    }
+
+    /// Averages each `factor` by `factor` block into one pixel, for
+    /// anti-aliasing by rendering large and shrinking. The size must be
+    /// a multiple of `factor`.
+    pub fn downsample(&self, factor: usize) -> Canvas {
+        assert!(factor > 0 && self.width.is_multiple_of(factor) && self.height.is_multiple_of(factor),
+            "Canvas size is not a multiple of the factor in Canvas::downsample()");
+        let (width, height) = (self.width / factor, self.height / factor);
+        let data = self.to_pixels();
+        let scale = 1.0 / (factor*factor) as f64;
+        let mut out = vec![[0.0; 4]; width*height];
+        for (i, p) in data.iter().enumerate() {
+            let (x, y) = (i % self.width / factor, i / self.width / factor);
+            out[y*width + x] = add(out[y*width + x], *p, scale);
+        }
+        self.sized_with_pixels(width, height, &out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{Colour,Rgba};
+    use crate::maths::almost_same;
+
+    fn numbered(width: usize, height: usize) -> Canvas {
+        let mut c = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                c.write_pixel(x, y, Colour::new(x as f64, y as f64, 0.0));
+            }
+        }
+        c
+    }
+
+    fn at(c: &Canvas, x: usize, y: usize) -> (f64, f64) {
+        let p = c.pixel_at(x, y);
+        (p.red, p.green)
+    }
+
+    #[test]
+    fn crop_and_flip() {
+        let c = numbered(5, 4);
+        let cropped = c.crop(1, 2, 3, 10);
+        assert_eq!((cropped.width(), cropped.height()), (3, 2));
+        assert_eq!(at(&cropped, 0, 0), (1.0, 2.0));
+        assert_eq!(at(&cropped, 2, 1), (3.0, 3.0));
+        assert_eq!(c.crop(9, 9, 2, 2).width(), 0);
+
+        assert_eq!(at(&c.flip_horizontal(), 0, 1), (4.0, 1.0));
+        assert_eq!(at(&c.flip_vertical(), 0, 1), (0.0, 2.0));
+    }
+
+    #[test]
+    fn rotations() {
+        let c = numbered(5, 4);
+        let r = c.rotate_90();
+        assert_eq!((r.width(), r.height()), (4, 5));
+        // the bottom left corner comes to the top left
+        assert_eq!(at(&r, 0, 0), (0.0, 3.0));
+        assert_eq!(at(&r, 3, 0), (0.0, 0.0));
+        assert_eq!(at(&c.rotate_180(), 0, 0), (4.0, 3.0));
+        assert_eq!(at(&c.rotate_270(), 0, 0), (4.0, 0.0));
+
+        let back = c.rotate_90().rotate_90().rotate_90().rotate_90();
+        assert_eq!(back.as_slice(), c.as_slice());
+        assert_eq!(c.rotate_90().rotate_270().as_slice(), c.as_slice());
+        assert_eq!(c.rotate_90().rotate_90().as_slice(), c.rotate_180().as_slice());
+    }
+
+    #[test]
+    fn filters() {
+        for filter in &[ResizeFilter::Bilinear, ResizeFilter::Bicubic, ResizeFilter::Lanczos3] {
+            assert_eq!(filter.weight(0.0), 1.0);
+            assert!(filter.weight(1.0).abs() < 1.0e-12);
+            assert_eq!(filter.weight(filter.radius()), 0.0);
+        }
+        for taps in contributions(10, 3, ResizeFilter::Lanczos3) {
+            assert!(almost_same(taps.iter().map(|t| t.1).sum(), 1.0));
+        }
+    }
+
+    #[test]
+    fn resizing() {
+        let mut flat = Canvas::new(7, 5);
+        for y in 0..5 {
+            for x in 0..7 {
+                flat.write_pixel(x, y, Colour::new(0.25, 0.5, 0.75));
+            }
+        }
+        for filter in &[ResizeFilter::Nearest, ResizeFilter::Bilinear, ResizeFilter::Bicubic, ResizeFilter::Lanczos3] {
+            for &(w, h) in &[(14, 10), (3, 2), (7, 5), (1, 1)] {
+                let r = flat.resize(w, h, *filter);
+                assert_eq!((r.width(), r.height()), (w, h));
+                assert!(r.as_slice().iter().all(|&p| p == Colour::new(0.25, 0.5, 0.75)), "{:?}", filter);
+                assert!(r.is_opaque());
+            }
+        }
+
+        // nearest doubles pixels
+        let c = numbered(3, 2);
+        let n = c.resize(6, 4, ResizeFilter::Nearest);
+        assert_eq!(at(&n, 0, 0), (0.0, 0.0));
+        assert_eq!(at(&n, 1, 1), (0.0, 0.0));
+        assert_eq!(at(&n, 5, 3), (2.0, 1.0));
+
+        // bilinear interpolates a ramp linearly away from the edges
+        let b = numbered(4, 1).resize(8, 1, ResizeFilter::Bilinear);
+        assert!(almost_same(b.pixel_at(3, 0).red, 1.25));
+        assert!(almost_same(b.pixel_at(4, 0).red, 1.75));
+
+        // shrinking by a whole factor with bilinear averages neighbours
+        let s = numbered(8, 1).resize(2, 1, ResizeFilter::Bilinear);
+        assert!(s.pixel_at(0, 0).red > 1.0 && s.pixel_at(0, 0).red < 2.0);
+    }
+
+    #[test]
+    fn downsampling() {
+        let c = numbered(4, 4);
+        let d = c.downsample(2);
+        assert_eq!((d.width(), d.height()), (2, 2));
+        assert_eq!(at(&d, 0, 0), (0.5, 0.5));
+        assert_eq!(at(&d, 1, 1), (2.5, 2.5));
+
+        // half-covered blocks come out half transparent
+        let mut edge = Canvas::new(2, 2);
+        edge.write_rgba(0, 0, Rgba::transparent());
+        edge.write_rgba(1, 0, Rgba::transparent());
+        let d = edge.downsample(2);
+        assert_eq!(d.alpha_at(0, 0), 0.5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn downsample_uneven() {
+        numbered(5, 4).downsample(2);
+    }
+}
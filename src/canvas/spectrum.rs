@@ -0,0 +1,331 @@
+use std::ops::{Add,Mul};
+use std::sync::OnceLock;
+use super::{Colour,Xyz};
+
+/// Shortest wavelength sampled, in nanometres.
+pub const WAVELENGTH_MIN: f64 = 380.0;
+/// Longest wavelength sampled, in nanometres.
+pub const WAVELENGTH_MAX: f64 = 780.0;
+/// Samples every 10 nm from `WAVELENGTH_MIN` to `WAVELENGTH_MAX`.
+pub const SPECTRUM_SAMPLES: usize = 41;
+const STEP: f64 = (WAVELENGTH_MAX - WAVELENGTH_MIN) / (SPECTRUM_SAMPLES - 1) as f64;
+
+/// A spectral power distribution or reflectance, sampled at evenly
+/// spaced wavelengths. Unlike `Colour`, products of spectra are
+/// physically meaningful: light times reflectance per wavelength.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Spectrum {
+    pub samples: [f64; SPECTRUM_SAMPLES],
+}
+
+impl Add for Spectrum {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut samples = self.samples;
+        for (s, o) in samples.iter_mut().zip(&other.samples) {
+            *s += o;
+        }
+        Self {samples}
+    }
+}
+
+impl Mul for Spectrum {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        let mut samples = self.samples;
+        for (s, o) in samples.iter_mut().zip(&other.samples) {
+            *s *= o;
+        }
+        Self {samples}
+    }
+}
+
+impl Mul<f64> for Spectrum {
+    type Output = Self;
+
+    fn mul(self, scalar: f64) -> Self {
+        let mut samples = self.samples;
+        for s in samples.iter_mut() {
+            *s *= scalar;
+        }
+        Self {samples}
+    }
+}
+
+/// Wavelength of sample `i` in nanometres.
+fn wavelength(i: usize) -> f64 {
+    WAVELENGTH_MIN + STEP * i as f64
+}
+
+/// Piecewise Gaussian used by the colour matching function fit.
+fn lobe(wavelength: f64, mu: f64, below: f64, above: f64) -> f64 {
+    let sigma = if wavelength < mu {below} else {above};
+    (-0.5 * ((wavelength - mu) / sigma).powi(2)).exp()
+}
+
+/// The CIE 1931 2° standard observer at `wavelength` nanometres, from
+/// the multi-lobe fit of Wyman, Sloan and Shirley (2013), which stays
+/// within the spread of the measured data.
+pub fn colour_matching(wavelength: f64) -> Xyz {
+    let l = wavelength;
+    Xyz::new(
+        1.056*lobe(l, 599.8, 37.9, 31.0) + 0.362*lobe(l, 442.0, 16.0, 26.7) - 0.065*lobe(l, 501.1, 20.4, 26.2),
+        0.821*lobe(l, 568.8, 46.9, 40.5) + 0.286*lobe(l, 530.9, 16.3, 31.1),
+        1.217*lobe(l, 437.0, 11.8, 36.0) + 0.681*lobe(l, 459.0, 26.0, 13.8))
+}
+
+/// Spectral radiance of a black body at `kelvin`, in W·sr⁻¹·m⁻²·nm⁻¹.
+pub fn planck(wavelength: f64, kelvin: f64) -> f64 {
+    const H: f64 = 6.626_070_15e-34;
+    const C: f64 = 299_792_458.0;
+    const K: f64 = 1.380_649e-23;
+    let l = wavelength * 1.0e-9;
+    2.0*H*C*C / l.powi(5) / ((H*C / (l*K*kelvin)).exp() - 1.0) * 1.0e-9
+}
+
+/// Refractive index from Cauchy's equation `a + b/λ²`, with `b` in µm²
+/// as usually tabulated; BK7 crown glass is about `a = 1.5046`,
+/// `b = 0.00420`.
+pub fn cauchy(a: f64, b: f64, wavelength: f64) -> f64 {
+    let micrometres = wavelength * 1.0e-3;
+    a + b / (micrometres * micrometres)
+}
+
+struct Tables {
+    /// Colour matching functions at each sample, scaled so that a
+    /// constant spectrum of 1 has `Y = 1`.
+    cmf: [Xyz; SPECTRUM_SAMPLES],
+    /// Linear RGB of the constant spectrum before white balancing.
+    white: Colour,
+    /// Spectra turning into pure red, green and blue.
+    basis: [Spectrum; 3],
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let mut cmf = [Xyz::new(0.0, 0.0, 0.0); SPECTRUM_SAMPLES];
+        for (i, c) in cmf.iter_mut().enumerate() {
+            *c = colour_matching(wavelength(i));
+        }
+        let norm: f64 = cmf.iter().map(|c| c.y).sum();
+        for c in cmf.iter_mut() {
+            *c = Xyz::new(c.x / norm, c.y / norm, c.z / norm);
+        }
+        let mut tables = Tables {
+            cmf,
+            white: Colour::new(1.0, 1.0, 1.0),
+            basis: [Spectrum::constant(0.0); 3],
+        };
+        tables.white = Colour::from_xyz(Spectrum::constant(1.0).xyz_with(&tables));
+        tables.basis = rgb_basis(&tables);
+        tables
+    })
+}
+
+/// Smooth long, middle and short wavelength bands summing to one,
+/// mixed so that each converts to exactly one primary. Because the
+/// bands sum to one, so do the mixed spectra, and white upsamples to a
+/// constant spectrum.
+fn rgb_basis(tables: &Tables) -> [Spectrum; 3] {
+    let step = |l: f64, edge: f64| 1.0 / (1.0 + (-(l - edge) / 6.0).exp());
+    let long = Spectrum::from_fn(|l| step(l, 590.0));
+    let short = Spectrum::from_fn(|l| 1.0 - step(l, 490.0));
+    let middle = Spectrum::constant(1.0) + long * -1.0 + short * -1.0;
+    let bands = [long, middle, short];
+
+    // columns are the colours of the bands
+    let colours: Vec<Colour> = bands.iter().map(|b| b.colour_with(tables)).collect();
+    let m = [
+        [colours[0].red, colours[1].red, colours[2].red],
+        [colours[0].green, colours[1].green, colours[2].green],
+        [colours[0].blue, colours[1].blue, colours[2].blue],
+    ];
+    let n = invert(m);
+    let mix = |i: usize| bands[0] * n[0][i] + bands[1] * n[1][i] + bands[2] * n[2][i];
+    [mix(0), mix(1), mix(2)]
+}
+
+fn invert(m: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let [[a, b, c], [d, e, f], [g, h, i]] = m;
+    let det = a*(e*i - f*h) - b*(d*i - f*g) + c*(d*h - e*g);
+    [
+        [(e*i - f*h) / det, (c*h - b*i) / det, (b*f - c*e) / det],
+        [(f*g - d*i) / det, (a*i - c*g) / det, (c*d - a*f) / det],
+        [(d*h - e*g) / det, (b*g - a*h) / det, (a*e - b*d) / det],
+    ]
+}
+
+impl Spectrum {
+    pub fn constant(value: f64) -> Self {
+        Self {samples: [value; SPECTRUM_SAMPLES]}
+    }
+
+    /// Samples `f(wavelength)` with the wavelength in nanometres.
+    pub fn from_fn(f: impl Fn(f64) -> f64) -> Self {
+        let mut samples = [0.0; SPECTRUM_SAMPLES];
+        for (i, s) in samples.iter_mut().enumerate() {
+            *s = f(wavelength(i));
+        }
+        Self {samples}
+    }
+
+    /// Upsamples a linear RGB reflectance or light colour. The result
+    /// converts back to exactly `c`, white becomes a constant spectrum,
+    /// and greys stay flat. Highly saturated colours dip slightly below
+    /// zero in places, as the sRGB primaries are not all reachable with
+    /// smooth positive spectra.
+    pub fn from_colour(c: Colour) -> Self {
+        let [r, g, b] = tables().basis;
+        r * c.red + g * c.green + b * c.blue
+    }
+
+    /// Black body emission at `kelvin`, scaled to a luminance of 1 so
+    /// that only its colour is fixed.
+    pub fn blackbody(kelvin: f64) -> Self {
+        let s = Spectrum::from_fn(|l| planck(l, kelvin));
+        s * (1.0 / s.to_xyz().y)
+    }
+
+    /// All the power at a single wavelength, as when tracing one
+    /// wavelength through a dispersive medium. Averaging lines at evenly
+    /// spread wavelengths, each with the same `power`, approaches
+    /// `Spectrum::constant(power)`.
+    pub fn line(wavelength: f64, power: f64) -> Self {
+        let mut samples = [0.0; SPECTRUM_SAMPLES];
+        let t = ((wavelength - WAVELENGTH_MIN) / STEP).clamp(0.0, (SPECTRUM_SAMPLES - 1) as f64);
+        let i = (t as usize).min(SPECTRUM_SAMPLES - 2);
+        let f = t - i as f64;
+        let scale = power * (SPECTRUM_SAMPLES - 1) as f64;
+        samples[i] = scale * (1.0 - f);
+        samples[i + 1] = scale * f;
+        Self {samples}
+    }
+
+    /// Linear interpolation between samples; zero outside the range.
+    pub fn at(&self, wavelength: f64) -> f64 {
+        if !(WAVELENGTH_MIN..=WAVELENGTH_MAX).contains(&wavelength) {
+            return 0.0;
+        }
+        let t = (wavelength - WAVELENGTH_MIN) / STEP;
+        let i = (t as usize).min(SPECTRUM_SAMPLES - 2);
+        let f = t - i as f64;
+        self.samples[i] * (1.0 - f) + self.samples[i + 1] * f
+    }
+
+    /// CIE XYZ, scaled so that a constant spectrum of 1 has `Y = 1`.
+    pub fn to_xyz(&self) -> Xyz {
+        self.xyz_with(tables())
+    }
+
+    /// Linear RGB, white balanced so that a constant spectrum of 1 is
+    /// white. Spectra from `from_colour` come back unchanged, and a
+    /// black body near 5500 K looks neutral.
+    pub fn to_colour(&self) -> Colour {
+        self.colour_with(tables())
+    }
+
+    fn xyz_with(&self, tables: &Tables) -> Xyz {
+        let mut xyz = Xyz::new(0.0, 0.0, 0.0);
+        for (s, c) in self.samples.iter().zip(&tables.cmf) {
+            xyz.x += s * c.x;
+            xyz.y += s * c.y;
+            xyz.z += s * c.z;
+        }
+        xyz
+    }
+
+    fn colour_with(&self, tables: &Tables) -> Colour {
+        let c = Colour::from_xyz(self.xyz_with(tables));
+        let w = tables.white;
+        Colour::new(c.red / w.red, c.green / w.green, c.blue / w.blue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maths::almost_same;
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() < tolerance
+    }
+
+    #[test]
+    fn matching_functions() {
+        // peaks of the tabulated CIE 1931 observer
+        assert!(close(colour_matching(600.0).x, 1.0622, 0.02));
+        assert!(close(colour_matching(555.0).y, 1.0000, 0.02));
+        assert!(close(colour_matching(445.0).z, 1.7826, 0.04));
+        assert!(close(colour_matching(700.0).y, 0.0041, 0.005));
+
+        let white = Spectrum::constant(1.0);
+        assert!(almost_same(white.to_xyz().y, 1.0));
+        assert_eq!(white.to_colour(), Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(Spectrum::constant(0.0).to_colour(), Colour::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn upsampling() {
+        for c in &[Colour::new(1.0, 0.0, 0.0), Colour::new(0.2, 0.5, 0.9), Colour::new(0.8, 0.8, 0.1), Colour::new(3.0, 2.0, 1.0)] {
+            assert_eq!(Spectrum::from_colour(*c).to_colour(), *c);
+        }
+        let grey = Spectrum::from_colour(Colour::new(0.5, 0.5, 0.5));
+        assert!(grey.samples.iter().all(|&s| almost_same(s, 0.5)));
+
+        // red reflects long wavelengths
+        let red = Spectrum::from_colour(Colour::new(1.0, 0.0, 0.0));
+        assert!(red.at(650.0) > 0.9 && red.at(450.0) < 0.1);
+        assert!(red.samples.iter().all(|&s| s > -0.2));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let s = Spectrum::from_fn(|l| l / 1000.0);
+        assert!(almost_same(s.at(500.0), 0.5));
+        assert!(almost_same(s.at(505.0), 0.505));
+        assert_eq!(s.at(300.0), 0.0);
+        assert!(almost_same((s * s).at(600.0), 0.36));
+        assert!(almost_same((s + s * 2.0).at(400.0), 1.2));
+
+        // white light on a reflectance gives the reflectance's colour
+        let c = Colour::new(0.3, 0.6, 0.2);
+        assert_eq!((Spectrum::constant(1.0) * Spectrum::from_colour(c)).to_colour(), c);
+    }
+
+    #[test]
+    fn blackbodies() {
+        // Wien's law puts the peak of 5000 K near 580 nm
+        let peak = (400..800).max_by(|&a, &b| planck(a as f64, 5000.0).total_cmp(&planck(b as f64, 5000.0)));
+        assert!(close(peak.unwrap() as f64, 579.6, 1.0));
+
+        let candle = Spectrum::blackbody(1900.0).to_colour();
+        let noon = Spectrum::blackbody(5500.0).to_colour();
+        let sky = Spectrum::blackbody(12000.0).to_colour();
+        assert!(almost_same(Spectrum::blackbody(3000.0).to_xyz().y, 1.0));
+        assert!(candle.red > candle.green && candle.green > candle.blue);
+        assert!(sky.blue > sky.green && sky.green > sky.red);
+        assert!(close(noon.red / noon.blue, 1.0, 0.1));
+    }
+
+    #[test]
+    fn dispersion() {
+        let n = 400;
+        let mut sum = Spectrum::constant(0.0);
+        for k in 0..n {
+            let l = WAVELENGTH_MIN + (WAVELENGTH_MAX - WAVELENGTH_MIN) * (k as f64 + 0.5) / n as f64;
+            sum = sum + Spectrum::line(l, 2.0) * (1.0 / n as f64);
+        }
+        assert!(sum.samples[1..SPECTRUM_SAMPLES - 1].iter().all(|&s| close(s, 2.0, 1.0e-9)));
+
+        let blue = cauchy(1.5046, 0.00420, 450.0);
+        let red = cauchy(1.5046, 0.00420, 650.0);
+        assert!(close(red, 1.5145, 1.0e-4));
+        assert!(blue > red);
+        // a line at 450 nm looks blue
+        let c = Spectrum::line(450.0, 1.0).to_colour();
+        assert!(c.blue > c.red && c.blue > c.green);
+    }
+}
@@ -0,0 +1,275 @@
+use std::io;
+use std::collections::HashMap;
+use super::{Canvas,Colour,Rgba};
+
+/// An image encoder fed one row at a time from the top, so that a
+/// picture can be written out as it is rendered rather than kept whole
+/// in a `Canvas`. The writers take any `std::io::Write` and hand it
+/// whole rows, so a bare `File` is fine.
+pub trait RowWriter {
+    fn width(&self) -> usize;
+
+    fn height(&self) -> usize;
+
+    /// Encodes the next row of `width()` premultiplied pixels.
+    fn write_rgba_row(&mut self, row: &[Rgba]) -> io::Result<()>;
+
+    /// Encodes the next row of opaque pixels.
+    fn write_row(&mut self, row: &[Colour]) -> io::Result<()> {
+        let row: Vec<Rgba> = row.iter().map(|&c| Rgba::from_colour(c, 1.0)).collect();
+        self.write_rgba_row(&row)
+    }
+
+    /// Completes the image after its last row and flushes the output.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Counts the rows given to a `RowWriter`, rejecting rows of the wrong
+/// length, too many rows, or finishing too early.
+pub(super) struct RowCounter {
+    pub width: usize,
+    pub height: usize,
+    pub y: usize,
+}
+
+impl RowCounter {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {width, height, y: 0}
+    }
+
+    /// Checks the next row, returning its `y`.
+    pub fn next(&mut self, len: usize) -> io::Result<usize> {
+        if len != self.width {
+            return Err(invalid_input("Row length differs from the image width"));
+        }
+        if self.y >= self.height {
+            return Err(invalid_input("More rows than the image height"));
+        }
+        self.y += 1;
+        Ok(self.y - 1)
+    }
+
+    pub fn check_complete(&self) -> io::Result<()> {
+        if self.y < self.height {
+            return Err(invalid_input("Image finished before its last row"));
+        }
+        Ok(())
+    }
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// A band of rows waiting for its tiles.
+struct Band {
+    pixels: Vec<Rgba>,
+    covered: Vec<bool>,
+    missing: usize,
+}
+
+/// Takes tiles finished in any order and passes each band of rows on to
+/// a `RowWriter` once all of it, and everything above it, is done. Only
+/// the bands still being rendered are held in memory.
+pub struct TileAssembler<R: RowWriter> {
+    writer: R,
+    band_height: usize,
+    bands: HashMap<usize, Band>,
+    next_band: usize,
+}
+
+impl<R: RowWriter> TileAssembler<R> {
+    /// Bands are `band_height` rows tall, normally the tile height.
+    pub fn new(writer: R, band_height: usize) -> Self {
+        assert!(band_height > 0, "Empty band in TileAssembler::new()");
+        Self {writer, band_height, bands: HashMap::new(), next_band: 0}
+    }
+
+    fn band_rows(&self, band: usize) -> usize {
+        usize::min(self.band_height, self.writer.height() - band*self.band_height)
+    }
+
+    /// Adds the pixels of `tile` with its top left corner at `(x, y)`.
+    /// Tiles must not overlap, even when the same one is written twice,
+    /// and each has to lie within one band.
+    pub fn write_tile(&mut self, x: usize, y: usize, tile: &Canvas) -> io::Result<()> {
+        if tile.width == 0 || tile.height == 0 {
+            return Ok(());
+        }
+        let band = y / self.band_height;
+        let (width, height) = (self.writer.width(), self.writer.height());
+        let fits = |start: usize, len: usize, limit: usize| start.checked_add(len).is_some_and(|end| end <= limit);
+        if !fits(x, tile.width, width) || !fits(y, tile.height, height) {
+            return Err(invalid_input("Tile lies outside the image"));
+        }
+        if (y + tile.height - 1) / self.band_height != band {
+            return Err(invalid_input("Tile crosses a band boundary"));
+        }
+        if band < self.next_band {
+            return Err(invalid_input("Tile in a band already written"));
+        }
+
+        let rows = self.band_rows(band);
+        let entry = self.bands.entry(band).or_insert_with(|| Band {
+            pixels: vec![Rgba::transparent(); width*rows],
+            covered: vec![false; width*rows],
+            missing: width*rows,
+        });
+        let top = y - band*self.band_height;
+        let spans = (top..top + tile.height).map(|row| row*width + x..row*width + x + tile.width);
+        if spans.clone().any(|span| entry.covered[span].contains(&true)) {
+            return Err(invalid_input("Tile overlaps one already written"));
+        }
+        for (ty, span) in spans.enumerate() {
+            entry.covered[span.clone()].fill(true);
+            for (tx, p) in entry.pixels[span].iter_mut().enumerate() {
+                *p = tile.rgba_at(tx, ty);
+            }
+        }
+        entry.missing -= tile.width*tile.height;
+
+        while self.bands.get(&self.next_band).is_some_and(|b| b.missing == 0) {
+            let band = self.bands.remove(&self.next_band).unwrap();
+            for row in band.pixels.chunks(width) {
+                self.writer.write_rgba_row(row)?;
+            }
+            self.next_band += 1;
+        }
+        Ok(())
+    }
+
+    /// Finishes the image, which must be complete, and returns the
+    /// writer.
+    pub fn finish(mut self) -> io::Result<R> {
+        self.writer.finish()?;
+        Ok(self.writer)
+    }
+}
+
+impl Canvas {
+    /// Streams the whole canvas through `writer` and finishes it.
+    pub fn write_rows(&self, writer: &mut impl RowWriter) -> io::Result<()> {
+        let mut row = Vec::with_capacity(self.width);
+        for y in 0..self.height {
+            row.clear();
+            row.extend((0..self.width).map(|x| self.rgba_at(x, y)));
+            writer.write_rgba_row(&row)?;
+        }
+        writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Records the rows it is given.
+    struct Rows {
+        counter: RowCounter,
+        rows: Vec<Vec<Rgba>>,
+        finished: bool,
+    }
+
+    impl RowWriter for Rows {
+        fn width(&self) -> usize {
+            self.counter.width
+        }
+
+        fn height(&self) -> usize {
+            self.counter.height
+        }
+
+        fn write_rgba_row(&mut self, row: &[Rgba]) -> io::Result<()> {
+            self.counter.next(row.len())?;
+            self.rows.push(row.to_vec());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            self.counter.check_complete()?;
+            self.finished = true;
+            Ok(())
+        }
+    }
+
+    fn rows(width: usize, height: usize) -> Rows {
+        Rows {counter: RowCounter::new(width, height), rows: Vec::new(), finished: false}
+    }
+
+    #[test]
+    fn row_checks() {
+        let mut r = rows(2, 1);
+        assert_eq!(r.write_row(&[Colour::new(0.0, 0.0, 0.0)]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(r.finish().is_err());
+        r.write_row(&[Colour::new(1.0, 0.0, 0.0); 2]).unwrap();
+        assert_eq!(r.rows[0][1], Rgba::new(1.0, 0.0, 0.0, 1.0));
+        assert!(r.write_row(&[Colour::new(0.0, 0.0, 0.0); 2]).is_err());
+        r.finish().unwrap();
+    }
+
+    #[test]
+    fn tiles_in_any_order() {
+        let (width, height) = (10, 7);
+        let mut image = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.write_rgba(x, y, Rgba::new(x as f64, y as f64, 0.0, 0.5));
+            }
+        }
+        // 4x3 tiles, bottom right first
+        let mut tiles = Vec::new();
+        for ty in (0..height).step_by(3) {
+            for tx in (0..width).step_by(4) {
+                tiles.push((tx, ty, image.crop(tx, ty, 4, 3)));
+            }
+        }
+        tiles.reverse();
+
+        let mut assembler = TileAssembler::new(rows(width, height), 3);
+        for (i, (x, y, tile)) in tiles.iter().enumerate() {
+            assembler.write_tile(*x, *y, tile).unwrap();
+            // nothing can be written until the top band is in
+            if i < tiles.len() - 3 {
+                assert!(assembler.writer.rows.is_empty());
+            }
+        }
+        assert!(assembler.bands.is_empty());
+        let out = assembler.finish().unwrap();
+        assert!(out.finished);
+        assert_eq!(out.rows.len(), height);
+        for (y, row) in out.rows.iter().enumerate() {
+            for (x, p) in row.iter().enumerate() {
+                assert_eq!(*p, image.rgba_at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn bad_tiles() {
+        let mut assembler = TileAssembler::new(rows(8, 8), 4);
+        let tile = Canvas::new(4, 4);
+        assert!(assembler.write_tile(6, 0, &tile).is_err());
+        assert!(assembler.write_tile(0, 2, &tile).is_err());
+        assert!(assembler.write_tile(usize::MAX, 0, &tile).is_err());
+        assert!(assembler.write_tile(0, usize::MAX - 2, &tile).is_err());
+        assembler.write_tile(0, 4, &tile).unwrap();
+        // writing a tile again, or one overlapping it, must not make the
+        // band look complete
+        assert_eq!(assembler.write_tile(0, 4, &tile).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(assembler.write_tile(2, 4, &tile).is_err());
+        assert!(assembler.bands[&1].covered[4..8].iter().all(|&c| !c));
+        assert_eq!(assembler.bands[&1].missing, 16);
+        // the image is incomplete
+        assert!(assembler.finish().is_err());
+    }
+
+    #[test]
+    fn canvas_rows() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(2, 1, Colour::new(0.5, 0.5, 0.5));
+        let mut r = rows(3, 2);
+        c.write_rows(&mut r).unwrap();
+        assert!(r.finished);
+        assert_eq!(r.rows[1][2], Rgba::new(0.5, 0.5, 0.5, 1.0));
+    }
+}
@@ -0,0 +1,141 @@
+use super::{Canvas,Colour};
+
+/// Operators compressing unbounded scene values towards [0, 1] before
+/// the 8-bit writers clamp them. The luminance based ones keep the hue,
+/// so a saturated colour can still have a channel above 1.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum Tonemap {
+    /// `L / (1 + L)` on luminance, keeping the hue.
+    Reinhard,
+    /// Reinhard with a white point: luminance `white` maps to 1 and
+    /// anything brighter above it. `white` has to be positive, which
+    /// `Canvas::tonemap` checks.
+    ReinhardExtended { white: f64 },
+    /// Narkowicz's fit of the ACES filmic curve, per channel.
+    Aces,
+    /// Hable's Uncharted 2 filmic curve, per channel, white point 11.2.
+    Hable,
+}
+
+/// Scales `c` so that its luminance becomes `mapped(luminance)`.
+fn map_luminance(c: Colour, mapped: impl Fn(f64) -> f64) -> Colour {
+    let l = c.luminance();
+    if l <= 0.0 {
+        return Colour::new(0.0, 0.0, 0.0);
+    }
+    c * (mapped(l) / l)
+}
+
+fn map_channels(c: Colour, mapped: impl Fn(f64) -> f64) -> Colour {
+    Colour::new(mapped(c.red.max(0.0)), mapped(c.green.max(0.0)), mapped(c.blue.max(0.0)))
+}
+
+fn aces(x: f64) -> f64 {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    ((x*(a*x + b)) / (x*(c*x + d) + e)).clamp(0.0, 1.0)
+}
+
+fn hable_partial(x: f64) -> f64 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    (x*(a*x + c*b) + d*e) / (x*(a*x + b) + d*f) - e/f
+}
+
+const HABLE_WHITE: f64 = 11.2;
+const HABLE_EXPOSURE_BIAS: f64 = 2.0;
+
+fn hable(x: f64) -> f64 {
+    (hable_partial(x * HABLE_EXPOSURE_BIAS) / hable_partial(HABLE_WHITE)).min(1.0)
+}
+
+impl Tonemap {
+    pub fn apply(&self, c: Colour) -> Colour {
+        match *self {
+            Tonemap::Reinhard => map_luminance(c, |l| l / (1.0 + l)),
+            Tonemap::ReinhardExtended { white } => {
+                map_luminance(c, |l| l * (1.0 + l / (white*white)) / (1.0 + l))
+            },
+            Tonemap::Aces => map_channels(c, aces),
+            Tonemap::Hable => map_channels(c, hable),
+        }
+    }
+}
+
+impl Canvas {
+    /// Scales every pixel by `2^ev`, as opening the aperture by `ev` stops.
+    pub fn adjust_exposure(&mut self, ev: f64) {
+        let scale = f64::powf(2.0, ev);
+        for p in self.pixels.iter_mut() {
+            *p = *p * scale;
+        }
+    }
+
+    /// Panics on a `ReinhardExtended` white point that is not positive.
+    pub fn tonemap(&mut self, op: Tonemap) {
+        if let Tonemap::ReinhardExtended { white } = op {
+            assert!(white > 0.0, "Non-positive white point in Canvas::tonemap()");
+        }
+        for p in self.pixels.iter_mut() {
+            *p = op.apply(*p);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maths::almost_same;
+
+    #[test]
+    fn reinhard() {
+        let grey = Colour::new(1.0, 1.0, 1.0);
+        assert_eq!(Tonemap::Reinhard.apply(grey), Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(Tonemap::Reinhard.apply(Colour::new(0.0, 0.0, 0.0)), Colour::new(0.0, 0.0, 0.0));
+        // hue is kept
+        let c = Tonemap::Reinhard.apply(Colour::new(4.0, 2.0, 1.0));
+        assert!(almost_same(c.red, 2.0 * c.green) && almost_same(c.green, 2.0 * c.blue));
+
+        let extended = Tonemap::ReinhardExtended { white: 4.0 };
+        assert_eq!(extended.apply(grey * 4.0), grey);
+        assert!(extended.apply(grey).red > Tonemap::Reinhard.apply(grey).red);
+
+        // single channels of saturated colours can exceed 1
+        assert!(Tonemap::Reinhard.apply(Colour::new(50.0, 0.0, 0.0)).red > 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Non-positive white point")]
+    fn zero_white_point() {
+        // checked up front, even with no pixels to map
+        Canvas::new(0, 0).tonemap(Tonemap::ReinhardExtended { white: 0.0 });
+    }
+
+    #[test]
+    fn filmic_curves() {
+        for op in &[Tonemap::Aces, Tonemap::Hable] {
+            let mut last = 0.0;
+            for i in 1..200 {
+                let v = op.apply(Colour::new(i as f64 * 0.1, 0.0, 0.0)).red;
+                assert!(v >= last && v <= 1.0, "{:?} at {}", op, i);
+                last = v;
+            }
+            assert!(last > 0.99);
+            assert!(op.apply(Colour::new(-1.0, 0.0, 0.0)).red.abs() < 1.0e-3);
+        }
+        assert!(almost_same(Tonemap::Aces.apply(Colour::new(100.0, 0.0, 0.0)).red, 1.0));
+        let white = HABLE_WHITE / HABLE_EXPOSURE_BIAS;
+        assert!(almost_same(Tonemap::Hable.apply(Colour::new(white, 0.0, 0.0)).red, 1.0));
+    }
+
+    #[test]
+    fn canvas_exposure_and_tonemap() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, Colour::new(0.25, 0.5, 1.0));
+        c.adjust_exposure(2.0);
+        assert_eq!(c.pixel_at(0, 0), Colour::new(1.0, 2.0, 4.0));
+        c.adjust_exposure(-1.0);
+        c.tonemap(Tonemap::Reinhard);
+        let l = Colour::new(0.5, 1.0, 2.0).luminance();
+        assert_eq!(c.pixel_at(0, 0), Colour::new(0.5, 1.0, 2.0) * (1.0 / (1.0 + l)));
+        assert_eq!(c.pixel_at(1, 0), Colour::new(0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,631 @@
+//! Just enough zlib (RFC 1950) and DEFLATE (RFC 1951) for the image
+//! codecs: stored and fixed-Huffman blocks on the way out, everything
+//! on the way in.
+
+use std::io;
+
+//////////////////////////////////////////////////////////////////////////
+/// Checksums
+///
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffff;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.update(data);
+    adler.value()
+}
+
+/// Adler-32 over data arriving in pieces.
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+impl Adler32 {
+    pub fn new() -> Self {
+        Self {a: 1, b: 0}
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        const MOD_ADLER: u32 = 65521;
+        // 5552 is the largest block for which b cannot overflow
+        for chunk in data.chunks(5552) {
+            for &byte in chunk {
+                self.a += byte as u32;
+                self.b += self.a;
+            }
+            self.a %= MOD_ADLER;
+            self.b %= MOD_ADLER;
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////
+/// Compression
+///
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum DeflateMode {
+    Stored,
+    FixedHuffman,
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: usize = 15;
+const MAX_CHAIN: usize = 64;
+
+/// Wraps raw DEFLATE data in a zlib stream.
+pub fn compress(data: &[u8], mode: DeflateMode) -> Vec<u8> {
+    // CMF: deflate with a 32K window; FLG: default level, no dictionary
+    let mut out = vec![0x78, 0x9c];
+    out.extend(deflate(data, mode));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub fn deflate(data: &[u8], mode: DeflateMode) -> Vec<u8> {
+    deflate_blocks(data, mode, true)
+}
+
+/// Blocks for `data`, marked final when `last` is set. Otherwise they
+/// end with an empty stored block so that the output stops on a byte
+/// boundary, as zlib's sync flush does.
+fn deflate_blocks(data: &[u8], mode: DeflateMode, last: bool) -> Vec<u8> {
+    match mode {
+        DeflateMode::Stored => deflate_stored(data, last),
+        DeflateMode::FixedHuffman => deflate_fixed(data, last),
+    }
+}
+
+fn deflate_stored(data: &[u8], last: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() + data.len() / 65535 * 5 + 5);
+    let mut chunks = data.chunks(65535).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[last as u8, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = last && chunks.peek().is_none();
+        out.push(last as u8);
+        let len = chunk.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out
+}
+
+/// A single fixed-Huffman block with LZ77 matches found through hash
+/// chains over the last 32K of input.
+fn deflate_fixed(data: &[u8], last: bool) -> Vec<u8> {
+    let mut w = BitWriter::new();
+    w.write_bits(last as u32, 1); // BFINAL
+    w.write_bits(1, 2); // BTYPE = fixed Huffman
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+    let hash = |i: usize| -> usize {
+        let v = (data[i] as usize) << 16 | (data[i + 1] as usize) << 8 | data[i + 2] as usize;
+        (v.wrapping_mul(2_654_435_761) >> 8) & ((1 << HASH_BITS) - 1)
+    };
+    let insert = |i: usize, head: &mut Vec<usize>, prev: &mut Vec<usize>| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(i);
+            prev[i % WINDOW_SIZE] = head[h];
+            head[h] = i;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best_len = 0;
+        let mut best_dist = 0;
+        if i + MIN_MATCH <= data.len() {
+            let max_len = usize::min(MAX_MATCH, data.len() - i);
+            let mut candidate = head[hash(i)];
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let mut len = 0;
+                while len < max_len && data[candidate + len] == data[i + len] {
+                    len += 1;
+                }
+                if len > best_len {
+                    best_len = len;
+                    best_dist = i - candidate;
+                    if len == max_len {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW_SIZE];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            write_fixed_length(&mut w, best_len);
+            write_fixed_distance(&mut w, best_dist);
+            for j in i..i + best_len {
+                insert(j, &mut head, &mut prev);
+            }
+            i += best_len;
+        } else {
+            write_fixed_literal(&mut w, data[i] as u16);
+            insert(i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    write_fixed_literal(&mut w, 256); // end of block
+    if last {
+        return w.finish();
+    }
+    w.write_bits(0, 3); // empty stored block, then its aligned lengths
+    let mut out = w.finish();
+    out.extend_from_slice(&[0, 0, 0xff, 0xff]);
+    out
+}
+
+/// A zlib stream compressed a piece at a time, for writers that can't
+/// hold all of their data. Each piece ends on a byte boundary and can
+/// be sent on straight away; matches don't reach back into earlier
+/// pieces.
+pub struct ZlibWriter {
+    adler: Adler32,
+    started: bool,
+}
+
+impl ZlibWriter {
+    pub fn new() -> Self {
+        Self {adler: Adler32::new(), started: false}
+    }
+
+    /// Compressed bytes for the next piece, Huffman coded or stored
+    /// whichever is smaller.
+    pub fn write(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        if !self.started {
+            out.extend_from_slice(&[0x78, 0x9c]);
+            self.started = true;
+        }
+        self.adler.update(data);
+        let blocks = [DeflateMode::FixedHuffman, DeflateMode::Stored].iter()
+            .map(|mode| deflate_blocks(data, *mode, false))
+            .min_by_key(|b| b.len())
+            .unwrap();
+        out.extend(blocks);
+        out
+    }
+
+    /// The end of the stream: a final empty block and the checksum.
+    pub fn finish(mut self) -> Vec<u8> {
+        let mut out = self.write(&[]);
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        out.extend_from_slice(&self.adler.value().to_be_bytes());
+        out
+    }
+}
+
+fn write_fixed_literal(w: &mut BitWriter, sym: u16) {
+    let (code, len) = match sym {
+        0..=143 => (0x30 + sym, 8),
+        144..=255 => (0x190 + sym - 144, 9),
+        256..=279 => (sym - 256, 7),
+        _ => (0xc0 + sym - 280, 8),
+    };
+    w.write_code(code as u32, len);
+}
+
+fn write_fixed_length(w: &mut BitWriter, len: usize) {
+    let idx = LENGTH_BASE.iter().rposition(|&b| b as usize <= len).unwrap();
+    write_fixed_literal(w, 257 + idx as u16);
+    w.write_bits((len - LENGTH_BASE[idx] as usize) as u32, LENGTH_EXTRA[idx] as u32);
+}
+
+fn write_fixed_distance(w: &mut BitWriter, dist: usize) {
+    let idx = DIST_BASE.iter().rposition(|&b| b as usize <= dist).unwrap();
+    w.write_code(idx as u32, 5);
+    w.write_bits((dist - DIST_BASE[idx] as usize) as u32, DIST_EXTRA[idx] as u32);
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    nbits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {out: Vec::new(), acc: 0, nbits: 0}
+    }
+
+    /// Writes `n` bits of `val`, least significant first.
+    fn write_bits(&mut self, val: u32, n: u32) {
+        self.acc |= (val as u64) << self.nbits;
+        self.nbits += n;
+        while self.nbits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.nbits -= 8;
+        }
+    }
+
+    /// Huffman codes are packed starting from their most significant bit.
+    fn write_code(&mut self, code: u32, len: u32) {
+        let reversed = code.reverse_bits() >> (32 - len);
+        self.write_bits(reversed, len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nbits > 0 {
+            self.out.push(self.acc as u8);
+        }
+        self.out
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////
+/// Decompression
+///
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Unwraps and inflates a zlib stream, checking its Adler-32. Streams
+/// inflating to more than `max_len` bytes are rejected.
+pub fn decompress(data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    if data.len() < 6 {
+        return Err(invalid("zlib stream too short"));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || !((cmf as u16) << 8 | flg as u16).is_multiple_of(31) {
+        return Err(invalid("Invalid zlib header"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(invalid("zlib preset dictionaries are not supported"));
+    }
+    let (out, used) = inflate(&data[2..], max_len)?;
+    let trailer = data.get(2 + used..2 + used + 4)
+        .ok_or_else(|| invalid("Missing zlib checksum"))?;
+    let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    if adler32(&out) != expected {
+        return Err(invalid("zlib checksum mismatch"));
+    }
+    Ok(out)
+}
+
+/// Inflates raw DEFLATE data, returning the output and the number of
+/// input bytes consumed. Fails as soon as the output would grow past
+/// `max_len` bytes.
+pub fn inflate(data: &[u8], max_len: usize) -> io::Result<(Vec<u8>, usize)> {
+    let mut r = BitReader {data, pos: 0, acc: 0, nbits: 0};
+    let mut out = Vec::new();
+    loop {
+        let last = r.bits(1)? == 1;
+        match r.bits(2)? {
+            0 => {
+                r.align();
+                let len = r.bits(16)?;
+                let nlen = r.bits(16)?;
+                if len != !nlen & 0xffff {
+                    return Err(invalid("Corrupt stored block length"));
+                }
+                check_len(&out, len as usize, max_len)?;
+                for _ in 0..len {
+                    out.push(r.bits(8)? as u8);
+                }
+            },
+            1 => {
+                let (lit, dist) = fixed_tables();
+                inflate_block(&mut r, &mut out, &lit, &dist, max_len)?;
+            },
+            2 => {
+                let (lit, dist) = dynamic_tables(&mut r)?;
+                inflate_block(&mut r, &mut out, &lit, &dist, max_len)?;
+            },
+            _ => return Err(invalid("Invalid DEFLATE block type")),
+        }
+        if last {
+            break;
+        }
+    }
+    r.align();
+    Ok((out, r.pos - (r.nbits / 8) as usize))
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u64,
+    nbits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.nbits < n {
+            let byte = *self.data.get(self.pos)
+                .ok_or_else(|| invalid("Unexpected end of DEFLATE data"))?;
+            self.acc |= (byte as u64) << self.nbits;
+            self.pos += 1;
+            self.nbits += 8;
+        }
+        let val = (self.acc & ((1u64 << n) - 1)) as u32;
+        self.acc >>= n;
+        self.nbits -= n;
+        Ok(val)
+    }
+
+    /// Drops the bits left over in the current byte.
+    fn align(&mut self) {
+        let drop = self.nbits % 8;
+        self.acc >>= drop;
+        self.nbits -= drop;
+    }
+}
+
+/// Canonical Huffman decoding table: code counts per length plus the
+/// symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = 2*left - count as i32;
+            if left < 0 {
+                return Err(invalid("Over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = sym as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Self {counts, symbols})
+    }
+
+    fn decode(&self, r: &mut BitReader) -> io::Result<u16> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..16 {
+            code |= r.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("Invalid Huffman code"))
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (sym, len) in lengths.iter_mut().enumerate() {
+        *len = match sym {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    let lit = Huffman::new(&lengths).unwrap();
+    let dist = Huffman::new(&[5; 30]).unwrap();
+    (lit, dist)
+}
+
+fn dynamic_tables(r: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    const ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+    let hlit = r.bits(5)? as usize + 257;
+    let hdist = r.bits(5)? as usize + 1;
+    let hclen = r.bits(4)? as usize + 4;
+
+    let mut cl_lengths = [0u8; 19];
+    for &idx in &ORDER[..hclen] {
+        cl_lengths[idx] = r.bits(3)? as u8;
+    }
+    let cl = Huffman::new(&cl_lengths)?;
+
+    let mut lengths = vec![0u8; hlit + hdist];
+    let mut i = 0;
+    while i < hlit + hdist {
+        let sym = cl.decode(r)?;
+        let (val, repeat) = match sym {
+            0..=15 => (sym as u8, 1),
+            16 => {
+                if i == 0 {
+                    return Err(invalid("Repeat with no previous code length"));
+                }
+                (lengths[i - 1], 3 + r.bits(2)? as usize)
+            },
+            17 => (0, 3 + r.bits(3)? as usize),
+            _ => (0, 11 + r.bits(7)? as usize),
+        };
+        if i + repeat > hlit + hdist {
+            return Err(invalid("Too many code lengths"));
+        }
+        for l in &mut lengths[i..i + repeat] {
+            *l = val;
+        }
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("Missing end-of-block code"));
+    }
+    Ok((Huffman::new(&lengths[..hlit])?, Huffman::new(&lengths[hlit..])?))
+}
+
+fn check_len(out: &[u8], extra: usize, max_len: usize) -> io::Result<()> {
+    if extra > max_len - out.len() {
+        return Err(invalid("DEFLATE data inflates past the expected length"));
+    }
+    Ok(())
+}
+
+fn inflate_block(r: &mut BitReader, out: &mut Vec<u8>, lit: &Huffman, dist: &Huffman, max_len: usize) -> io::Result<()> {
+    loop {
+        let sym = lit.decode(r)? as usize;
+        if sym < 256 {
+            check_len(out, 1, max_len)?;
+            out.push(sym as u8);
+        } else if sym == 256 {
+            return Ok(());
+        } else {
+            let idx = sym - 257;
+            if idx >= LENGTH_BASE.len() {
+                return Err(invalid("Invalid length symbol"));
+            }
+            let len = LENGTH_BASE[idx] as usize + r.bits(LENGTH_EXTRA[idx] as u32)? as usize;
+            let dsym = dist.decode(r)? as usize;
+            if dsym >= DIST_BASE.len() {
+                return Err(invalid("Invalid distance symbol"));
+            }
+            let d = DIST_BASE[dsym] as usize + r.bits(DIST_EXTRA[dsym] as u32)? as usize;
+            if d > out.len() {
+                return Err(invalid("Distance too far back"));
+            }
+            check_len(out, len, max_len)?;
+            let start = out.len() - d;
+            // byte by byte, since the match may overlap its own output
+            for k in 0..len {
+                out.push(out[start + k]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut data = Vec::new();
+        for i in 0..100_000u32 {
+            data.push((i % 251) as u8 ^ (i / 1000) as u8);
+        }
+        data.extend(std::iter::repeat_n(7u8, 5000));
+        for input in &[&b""[..], &b"a"[..], &b"abcabcabcabcabcabc"[..], &data[..]] {
+            for mode in &[DeflateMode::Stored, DeflateMode::FixedHuffman] {
+                let z = compress(input, *mode);
+                assert_eq!(decompress(&z, usize::MAX).unwrap(), input.to_vec());
+            }
+        }
+        assert!(compress(&data, DeflateMode::FixedHuffman).len() < data.len() / 4);
+    }
+
+    #[test]
+    fn streaming() {
+        let mut data = Vec::new();
+        for i in 0..50_000u32 {
+            data.push((i % 97) as u8);
+        }
+        let mut zlib = ZlibWriter::new();
+        let mut z = Vec::new();
+        for piece in data.chunks(7000) {
+            z.extend(zlib.write(piece));
+        }
+        z.extend(zlib.finish());
+        assert_eq!(decompress(&z, usize::MAX).unwrap(), data);
+        assert!(z.len() < data.len() / 4);
+
+        let mut adler = Adler32::new();
+        adler.update(b"Wiki");
+        adler.update(b"pedia");
+        assert_eq!(adler.value(), 0x11e6_0398);
+        assert_eq!(decompress(&ZlibWriter::new().finish(), 0).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn inflate_dynamic() {
+        // zlib at level 9 picks a dynamic Huffman block for this text
+        let z = [
+            0x78, 0xda, 0xb5, 0xcb, 0xd1, 0x01, 0x80, 0x10, 0x14, 0x46, 0xe1, 0xf7,
+            0xa6, 0xf8, 0x27, 0x30, 0x4b, 0x0f, 0x16, 0x50, 0x11, 0x15, 0x37, 0x84,
+            0x98, 0xbe, 0xbb, 0x44, 0xcf, 0xe7, 0x3b, 0xd2, 0x6a, 0xc4, 0xe2, 0xd6,
+            0x13, 0x4b, 0xa2, 0x16, 0x60, 0xe8, 0xc5, 0x51, 0xfc, 0x9d, 0x41, 0x55,
+            0x27, 0x3c, 0x9c, 0x2f, 0x35, 0x3a, 0x36, 0xda, 0x05, 0xe4, 0x6f, 0x78,
+            0x56, 0xec, 0x7c, 0xc7, 0xc2, 0xa8, 0xb9, 0xc7, 0xc2, 0xb8, 0xaa, 0x39,
+            0x0d, 0x1d, 0x70, 0xb9, 0x58, 0x28, 0xf1, 0xbb, 0x67, 0x31, 0x7d, 0xf2,
+            0x12, 0x3f, 0x17];
+        let mut expected = b"The quick brown fox jumps over the lazy dog. ".repeat(3);
+        expected.extend_from_slice(b"Pack my box with five dozen liquor jugs.\n");
+        assert_eq!(decompress(&z, usize::MAX).unwrap(), expected);
+    }
+
+    #[test]
+    fn corrupt_stream() {
+        let mut z = compress(b"some data to protect", DeflateMode::FixedHuffman);
+        let n = z.len();
+        z[n - 1] ^= 1;
+        assert!(decompress(&z, usize::MAX).is_err());
+        assert!(decompress(&[0x78, 0x9c], usize::MAX).is_err());
+        assert!(decompress(&[0x78, 0x9d, 0, 0, 0, 0], usize::MAX).is_err());
+
+        // output past the limit is rejected, whether literal, matched or stored
+        let data = vec![3u8; 1000];
+        for mode in &[DeflateMode::Stored, DeflateMode::FixedHuffman] {
+            let z = compress(&data, *mode);
+            assert_eq!(decompress(&z, 1000).unwrap(), data);
+            assert!(decompress(&z, 999).is_err());
+            assert!(decompress(&z, 0).is_err());
+        }
+    }
+}
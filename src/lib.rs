@@ -1,2 +1,6 @@
 pub mod maths;
-pub mod canvas;
\ No newline at end of file
+pub mod canvas;
+pub mod ray;
+pub mod lighting;
+pub mod world;
+pub mod camera;
\ No newline at end of file
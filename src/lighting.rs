@@ -0,0 +1,100 @@
+use crate::maths::Tuple;
+use crate::canvas::Colour;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct PointLight {
+    pub position: Tuple,
+    pub intensity: Colour,
+}
+
+impl PointLight {
+    pub fn new(position: Tuple, intensity: Colour) -> Self {
+        Self {position, intensity}
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Material {
+    pub colour: Colour,
+    pub ambient: f64,
+    pub diffuse: f64,
+    pub specular: f64,
+    pub shininess: f64,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            colour: Colour::new(1.0, 1.0, 1.0),
+            ambient: 0.1,
+            diffuse: 0.9,
+            specular: 0.9,
+            shininess: 200.0,
+        }
+    }
+}
+
+/// Phong reflection model. `eye` and `normal` must be normalised
+/// vectors pointing away from the surface at `point`.
+pub fn lighting(material: &Material, light: &PointLight,
+    point: Tuple, eye: Tuple, normal: Tuple) -> Colour {
+    let black = Colour::new(0.0, 0.0, 0.0);
+    let effective_colour = material.colour * light.intensity;
+    let light_dir = (light.position - point).normalised();
+    let ambient = effective_colour * material.ambient;
+
+    // A negative cosine means the light is behind the surface
+    let light_dot_normal = light_dir.dot(normal);
+    if light_dot_normal < 0.0 {
+        return ambient;
+    }
+    let diffuse = effective_colour * material.diffuse * light_dot_normal;
+
+    let reflect_dir = (-light_dir).reflect(normal);
+    let reflect_dot_eye = reflect_dir.dot(eye);
+    let specular = if reflect_dot_eye <= 0.0 {
+        black
+    } else {
+        let factor = reflect_dot_eye.powf(material.shininess);
+        light.intensity * material.specular * factor
+    };
+
+    ambient + diffuse + specular
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maths::{point,vector};
+
+    #[test]
+    fn lighting_angles() {
+        let m = Material::default();
+        let position = point(0.0, 0.0, 0.0);
+        let normal = vector(0.0, 0.0, -1.0);
+        let h = f64::sqrt(2.0)/2.0;
+        let white = Colour::new(1.0, 1.0, 1.0);
+
+        let eye = vector(0.0, 0.0, -1.0);
+        let light = PointLight::new(point(0.0, 0.0, -10.0), white);
+        assert_eq!(lighting(&m, &light, position, eye, normal), Colour::new(1.9, 1.9, 1.9));
+
+        let eye = vector(0.0, h, -h);
+        assert_eq!(lighting(&m, &light, position, eye, normal), Colour::new(1.0, 1.0, 1.0));
+
+        let eye = vector(0.0, 0.0, -1.0);
+        let light = PointLight::new(point(0.0, 10.0, -10.0), white);
+        let c = lighting(&m, &light, position, eye, normal);
+        let expected = 0.1 + 0.9*h;
+        assert_eq!(c, Colour::new(expected, expected, expected));
+
+        let eye = vector(0.0, -h, -h);
+        let c = lighting(&m, &light, position, eye, normal);
+        let expected = 0.1 + 0.9*h + 0.9;
+        assert_eq!(c, Colour::new(expected, expected, expected));
+
+        let eye = vector(0.0, 0.0, -1.0);
+        let light = PointLight::new(point(0.0, 0.0, 10.0), white);
+        assert_eq!(lighting(&m, &light, position, eye, normal), Colour::new(0.1, 0.1, 0.1));
+    }
+}
@@ -1,43 +1,78 @@
-use raytracer::canvas::Canvas;
-use raytracer::canvas::Colour;
-use raytracer::maths::{Matrix4,Matrix3,almost_same};
-
-fn paint_square(c: &mut Canvas, col: Colour, x0: usize, y0: usize, x1: usize, y1: usize) {
-    for y in y0..y1 {
-        for x in x0..x1 {
-            c.write_pixel(x, y, col);
-        }
-    }
-}
+use std::f64::consts::PI;
+use std::io::IsTerminal;
+use raytracer::canvas::{Colour,AnsiColours};
+use raytracer::maths::{point,vector,Matrix4};
+use raytracer::ray::Sphere;
+use raytracer::lighting::{Material,PointLight};
+use raytracer::world::World;
+use raytracer::camera::Camera;
 
 fn main() {
-    let mut c = Canvas::new(512, 512);
-    let blue = Colour::new(0.0, 0.0, 1.0);
-    let red = Colour::new(1.0, 0.0, 0.0);
-    paint_square(&mut c, blue, 0, 0, 128, 128);
-    paint_square(&mut c, red, 384, 384, 512, 512);
-    paint_square(&mut c, blue, 450, 0, 512, 62);
-//    c.to_bmp("tst.bmp").expect("Failed to create .bmp file");
+    let wall = Material {
+        colour: Colour::new(1.0, 0.9, 0.9),
+        specular: 0.0,
+        ..Material::default()
+    };
+    let floor = Sphere::new()
+        .with_material(wall)
+        .with_transform(Matrix4::scaling(10.0, 0.01, 10.0));
+    let left_wall = Sphere::new()
+        .with_material(wall)
+        .with_transform(Matrix4::identity()
+            .scale(10.0, 0.01, 10.0)
+            .rotate_x(PI/2.0)
+            .rotate_y(-PI/4.0)
+            .translate(0.0, 0.0, 5.0));
+    let right_wall = Sphere::new()
+        .with_material(wall)
+        .with_transform(Matrix4::identity()
+            .scale(10.0, 0.01, 10.0)
+            .rotate_x(PI/2.0)
+            .rotate_y(PI/4.0)
+            .translate(0.0, 0.0, 5.0));
 
-    let m3 = Matrix3::new(
-        1.0, 2.0, 6.0,
-        -5.0, 8.0, -4.0,
-        2.0, 6.0, 4.0);
-    assert!(almost_same(m3.cofactor(0,0), 56.0));        
-    assert!(almost_same(m3.cofactor(0,1), 12.0));        
-    let cof = m3.cofactor(0,2);
-    assert!(almost_same(cof, -46.0));        
-    assert!(almost_same(m3.det(), -196.0));  
+    let middle = Sphere::new()
+        .with_material(Material {
+            colour: Colour::new(0.1, 1.0, 0.5),
+            diffuse: 0.7,
+            specular: 0.3,
+            ..Material::default()
+        })
+        .with_transform(Matrix4::translation(-0.5, 1.0, 0.5));
+    let right = Sphere::new()
+        .with_material(Material {
+            colour: Colour::new(0.5, 1.0, 0.1),
+            diffuse: 0.7,
+            specular: 0.3,
+            ..Material::default()
+        })
+        .with_transform(Matrix4::identity()
+            .scale(0.5, 0.5, 0.5)
+            .translate(1.5, 0.5, -0.5));
+    let left = Sphere::new()
+        .with_material(Material {
+            colour: Colour::new(1.0, 0.8, 0.1),
+            diffuse: 0.7,
+            specular: 0.3,
+            ..Material::default()
+        })
+        .with_transform(Matrix4::identity()
+            .scale(0.33, 0.33, 0.33)
+            .translate(-1.5, 0.33, -0.75));
 
-    let m4 = Matrix4::new(
-        -2.0 , -8.0 ,  3.0 ,  5.0 ,
-        -3.0 ,  1.0 ,  7.0 ,  3.0 ,
-        1.0 ,  2.0 , -9.0 ,  6.0 ,
-        -6.0 ,  7.0 ,  7.0 , -9.0);
-    assert!(almost_same(m4.cofactor(0,0), 690.0));        
-    assert!(almost_same(m4.cofactor(0,1), 447.0));        
-    assert!(almost_same(m4.cofactor(0,2), 210.0));        
-    assert!(almost_same(m4.cofactor(0,3), 51.0));        
-    assert!(almost_same(m4.det(), -4071.0));  
+    let world = World {
+        objects: vec![floor, left_wall, right_wall, middle, right, left],
+        lights: vec![PointLight::new(point(-10.0, 10.0, -10.0), Colour::new(1.0, 1.0, 1.0))],
+    };
+    let camera = Camera::new(512, 256, PI/3.0)
+        .with_transform(Matrix4::view_transform(
+            point(0.0, 1.5, -5.0),
+            point(0.0, 1.0, 0.0),
+            vector(0.0, 1.0, 0.0)));
 
+    let c = camera.render(&world);
+    c.to_bmp("scene.bmp").expect("Failed to create .bmp file");
+    if std::io::stdout().is_terminal() {
+        print!("{}", c.to_ansi_with(80, AnsiColours::detect()));
+    }
 }
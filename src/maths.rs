@@ -144,6 +144,10 @@ impl Tuple {
             self.z*other.x - self.x*other.z,
             self.x*other.y - self.y*other.x)
     }
+
+    pub fn reflect(self, normal: Self) -> Self {
+        self - normal * 2.0 * self.dot(normal)
+    }
 }
 
 pub fn vector(x: f64, y: f64, z: f64) -> Tuple {
@@ -181,7 +185,7 @@ pub struct Matrix2 {
 
 impl PartialEq for Matrix4 {
     fn eq(&self, other: &Self) -> bool {
-        return almost_same(self.m00, other.m00)
+        almost_same(self.m00, other.m00)
             && almost_same(self.m01, other.m01) 
             && almost_same(self.m02, other.m02)
             && almost_same(self.m03, other.m03)
@@ -199,13 +203,13 @@ impl PartialEq for Matrix4 {
             && almost_same(self.m30, other.m30) 
             && almost_same(self.m31, other.m31) 
             && almost_same(self.m32, other.m32)
-            && almost_same(self.m33, other.m33);
+            && almost_same(self.m33, other.m33)
     }
 }
 
 impl PartialEq for Matrix3 {
     fn eq(&self, other: &Self) -> bool {
-        return almost_same(self.m00, other.m00)
+        almost_same(self.m00, other.m00)
             && almost_same(self.m01, other.m01) 
             && almost_same(self.m02, other.m02)
 
@@ -215,17 +219,17 @@ impl PartialEq for Matrix3 {
 
             && almost_same(self.m20, other.m20) 
             && almost_same(self.m21, other.m21) 
-            && almost_same(self.m22, other.m22);
+            && almost_same(self.m22, other.m22)
     }
 }
 
 impl PartialEq for Matrix2 {
     fn eq(&self, other: &Self) -> bool {
-        return almost_same(self.m00, other.m00)
+        almost_same(self.m00, other.m00)
             && almost_same(self.m01, other.m01) 
 
             && almost_same(self.m10, other.m10) 
-            && almost_same(self.m11, other.m11);
+            && almost_same(self.m11, other.m11)
     }
 }
 
@@ -264,6 +268,7 @@ impl Mul<Tuple> for Matrix4 {
 }
 
 impl Matrix4 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         m00: f64, m01: f64, m02: f64, m03: f64,
         m10: f64, m11: f64, m12: f64, m13: f64,
@@ -382,15 +387,141 @@ impl Matrix4 {
 
     pub fn cofactor(&self, row: usize, col: usize) -> f64 {
         let x = self.minor(row, col);
-        if (row + col) % 2 == 0 {
+        if (row + col).is_multiple_of(2) {
             x
         } else {
             -x
         }
     }
+
+    /// Returns `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.det();
+        if almost_same(det, 0.0) {
+            return None;
+        }
+        let mut m = Self::zero();
+        for i in 0..4 {
+            for j in 0..4 {
+                // transposed: the inverse is the adjugate over the determinant
+                m.set(self.cofactor(i, j) / det, j, i);
+            }
+        }
+        Some(m)
+    }
+
+    pub fn translation(x: f64, y: f64, z: f64) -> Self {
+        Self::new(
+            1.0, 0.0, 0.0, x,
+            0.0, 1.0, 0.0, y,
+            0.0, 0.0, 1.0, z,
+            0.0, 0.0, 0.0, 1.0)
+    }
+
+    pub fn scaling(x: f64, y: f64, z: f64) -> Self {
+        Self::new(
+            x, 0.0, 0.0, 0.0,
+            0.0, y, 0.0, 0.0,
+            0.0, 0.0, z, 0.0,
+            0.0, 0.0, 0.0, 1.0)
+    }
+
+    pub fn rotation_x(angle: f64) -> Self {
+        let (s, c) = angle.sin_cos();
+        Self::new(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, c, -s, 0.0,
+            0.0, s, c, 0.0,
+            0.0, 0.0, 0.0, 1.0)
+    }
+
+    pub fn rotation_y(angle: f64) -> Self {
+        let (s, c) = angle.sin_cos();
+        Self::new(
+            c, 0.0, s, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            -s, 0.0, c, 0.0,
+            0.0, 0.0, 0.0, 1.0)
+    }
+
+    pub fn rotation_z(angle: f64) -> Self {
+        let (s, c) = angle.sin_cos();
+        Self::new(
+            c, -s, 0.0, 0.0,
+            s, c, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Rotation by `angle` about `axis` (Rodrigues' formula).
+    /// The axis need not be normalised.
+    pub fn rotation(axis: Tuple, angle: f64) -> Self {
+        let a = vector(axis.x, axis.y, axis.z).normalised();
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+        Self::new(
+            t*a.x*a.x + c, t*a.x*a.y - s*a.z, t*a.x*a.z + s*a.y, 0.0,
+            t*a.x*a.y + s*a.z, t*a.y*a.y + c, t*a.y*a.z - s*a.x, 0.0,
+            t*a.x*a.z - s*a.y, t*a.y*a.z + s*a.x, t*a.z*a.z + c, 0.0,
+            0.0, 0.0, 0.0, 1.0)
+    }
+
+    pub fn shearing(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Self {
+        Self::new(
+            1.0, xy, xz, 0.0,
+            yx, 1.0, yz, 0.0,
+            zx, zy, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// World-to-eye transform for an eye at `from` looking at `to`.
+    pub fn view_transform(from: Tuple, to: Tuple, up: Tuple) -> Self {
+        let forward = (to - from).normalised();
+        let left = forward.cross(up.normalised());
+        let true_up = left.cross(forward);
+        let orientation = Self::new(
+            left.x, left.y, left.z, 0.0,
+            true_up.x, true_up.y, true_up.z, 0.0,
+            -forward.x, -forward.y, -forward.z, 0.0,
+            0.0, 0.0, 0.0, 1.0);
+        orientation * Self::translation(-from.x, -from.y, -from.z)
+    }
+
+    // Chaining versions of the above: each one applies its transform
+    // after `self`, so `identity().rotate_x(a).translate(x, y, z)`
+    // rotates first and then translates.
+
+    pub fn translate(self, x: f64, y: f64, z: f64) -> Self {
+        Self::translation(x, y, z) * self
+    }
+
+    pub fn scale(self, x: f64, y: f64, z: f64) -> Self {
+        Self::scaling(x, y, z) * self
+    }
+
+    pub fn rotate_x(self, angle: f64) -> Self {
+        Self::rotation_x(angle) * self
+    }
+
+    pub fn rotate_y(self, angle: f64) -> Self {
+        Self::rotation_y(angle) * self
+    }
+
+    pub fn rotate_z(self, angle: f64) -> Self {
+        Self::rotation_z(angle) * self
+    }
+
+    pub fn rotate(self, axis: Tuple, angle: f64) -> Self {
+        Self::rotation(axis, angle) * self
+    }
+
+    pub fn shear(self, xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Self {
+        Self::shearing(xy, xz, yx, yz, zx, zy) * self
+    }
 }
 
 impl Matrix3 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         m00: f64, m01: f64, m02: f64,
         m10: f64, m11: f64, m12: f64,
@@ -470,7 +601,7 @@ impl Matrix3 {
 
     pub fn cofactor(&self, row: usize, col: usize) -> f64 {
         let x = self.minor(row, col);
-        if (row + col) % 2 == 0 {
+        if (row + col).is_multiple_of(2) {
             x
         } else {
             -x
@@ -588,6 +719,18 @@ mod tst {
         assert_eq!(Tuple::cross(v2, v1), vector(1.0,-2.0,1.0));
     }
 
+    #[test]
+    fn reflect() {
+        let v = vector(1.0, -1.0, 0.0);
+        let n = vector(0.0, 1.0, 0.0);
+        assert_eq!(v.reflect(n), vector(1.0, 1.0, 0.0));
+
+        let v = vector(0.0, -1.0, 0.0);
+        let h = f64::sqrt(2.0)/2.0;
+        let n = vector(h, h, 0.0);
+        assert_eq!(v.reflect(n), vector(1.0, 0.0, 0.0));
+    }
+
     #[test]
     fn matrix() {
         let m4 = Matrix4::new(
@@ -738,4 +881,96 @@ mod tst {
         assert!(almost_same(m4.cofactor(0,3), 51.0));        
         assert!(almost_same(m4.det(), -4071.0));  
     }
-}
\ No newline at end of file
+    #[test]
+    fn inverse() {
+        let m4 = Matrix4::new(
+            -5.0,  2.0,  6.0, -8.0,
+             1.0, -5.0,  1.0,  8.0,
+             7.0,  7.0, -6.0, -7.0,
+             1.0, -3.0,  7.0,  4.0);
+        let inv = m4.inverse().unwrap();
+        assert!(almost_same(m4.det(), 532.0));
+        assert!(almost_same(inv.m32, -160.0/532.0));
+        assert!(almost_same(inv.m23, 105.0/532.0));
+        assert_eq!(m4 * inv, Matrix4::identity());
+        assert_eq!(inv * m4, Matrix4::identity());
+
+        let singular = Matrix4::new(
+            -4.0,  2.0, -2.0, -3.0,
+             9.0,  6.0,  2.0,  6.0,
+             0.0, -5.0,  1.0, -5.0,
+             0.0,  0.0,  0.0,  0.0);
+        assert!(singular.inverse().is_none());
+
+        let m4_ = Matrix4::new(
+            8.0,  2.0, 2.0, 2.0,
+            3.0, -1.0, 7.0, 0.0,
+            7.0,  0.0, 5.0, 4.0,
+            6.0, -2.0, 0.0, 5.0);
+        let prod = m4 * m4_;
+        assert_eq!(prod * m4_.inverse().unwrap(), m4);
+    }
+
+    #[test]
+    fn transforms() {
+        use std::f64::consts::PI;
+
+        let p = point(-3.0, 4.0, 5.0);
+        assert_eq!(Matrix4::translation(5.0, -3.0, 2.0) * p, point(2.0, 1.0, 7.0));
+        let v = vector(-3.0, 4.0, 5.0);
+        assert_eq!(Matrix4::translation(5.0, -3.0, 2.0) * v, v);
+
+        let s = Matrix4::scaling(2.0, 3.0, 4.0);
+        assert_eq!(s * point(-4.0, 6.0, 8.0), point(-8.0, 18.0, 32.0));
+        assert_eq!(s.inverse().unwrap() * vector(-4.0, 6.0, 8.0), vector(-2.0, 2.0, 2.0));
+
+        let p = point(0.0, 1.0, 0.0);
+        let h = f64::sqrt(2.0)/2.0;
+        assert_eq!(Matrix4::rotation_x(PI/4.0) * p, point(0.0, h, h));
+        assert_eq!(Matrix4::rotation_x(PI/2.0) * p, point(0.0, 0.0, 1.0));
+        assert_eq!(Matrix4::rotation_y(PI/2.0) * point(0.0, 0.0, 1.0), point(1.0, 0.0, 0.0));
+        assert_eq!(Matrix4::rotation_z(PI/2.0) * p, point(-1.0, 0.0, 0.0));
+
+        let axis = vector(1.0, 1.0, 1.0);
+        assert_eq!(Matrix4::rotation(vector(1.0, 0.0, 0.0), 0.7), Matrix4::rotation_x(0.7));
+        assert_eq!(Matrix4::rotation(vector(0.0, 2.0, 0.0), 0.7), Matrix4::rotation_y(0.7));
+        assert_eq!(Matrix4::rotation(vector(0.0, 0.0, 1.0), 0.7), Matrix4::rotation_z(0.7));
+        assert_eq!(Matrix4::rotation(axis, 2.0*PI/3.0) * point(1.0, 0.0, 0.0), point(0.0, 1.0, 0.0));
+
+        let p = point(2.0, 3.0, 4.0);
+        assert_eq!(Matrix4::shearing(1.0, 0.0, 0.0, 0.0, 0.0, 0.0) * p, point(5.0, 3.0, 4.0));
+        assert_eq!(Matrix4::shearing(0.0, 0.0, 0.0, 0.0, 0.0, 1.0) * p, point(2.0, 3.0, 7.0));
+
+        let chained = Matrix4::identity()
+            .rotate_x(PI/2.0)
+            .scale(5.0, 5.0, 5.0)
+            .translate(10.0, 5.0, 7.0);
+        assert_eq!(chained * point(1.0, 0.0, 1.0), point(15.0, 0.0, 7.0));
+        let explicit = Matrix4::translation(10.0, 5.0, 7.0)
+            * Matrix4::scaling(5.0, 5.0, 5.0)
+            * Matrix4::rotation_x(PI/2.0);
+        assert_eq!(chained, explicit);
+    }
+
+    #[test]
+    fn view_transform() {
+        let t = Matrix4::view_transform(point(0.0, 0.0, 0.0), point(0.0, 0.0, -1.0), vector(0.0, 1.0, 0.0));
+        assert_eq!(t, Matrix4::identity());
+        let t = Matrix4::view_transform(point(0.0, 0.0, 0.0), point(0.0, 0.0, 1.0), vector(0.0, 1.0, 0.0));
+        assert_eq!(t, Matrix4::scaling(-1.0, 1.0, -1.0));
+        let t = Matrix4::view_transform(point(0.0, 0.0, 8.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        assert_eq!(t, Matrix4::translation(0.0, 0.0, -8.0));
+
+        let t = Matrix4::view_transform(point(1.0, 3.0, 2.0), point(4.0, -2.0, 8.0), vector(1.0, 1.0, 0.0));
+        let expected = Matrix4::new(
+            -0.50709, 0.50709,  0.67612, -2.36643,
+             0.76772, 0.60609,  0.12122, -2.82843,
+            -0.35857, 0.59761, -0.71714,  0.00000,
+             0.00000, 0.00000,  0.00000,  1.00000);
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!(f64::abs(t.at(i, j) - expected.at(i, j)) < 1.0e-5);
+            }
+        }
+    }
+}
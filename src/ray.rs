@@ -0,0 +1,311 @@
+use std::ops::Index;
+use crate::maths::{Tuple,Matrix4,point,vector};
+use crate::lighting::Material;
+
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Ray {
+    pub origin: Tuple,
+    pub direction: Tuple,
+}
+
+impl Ray {
+    pub fn new(origin: Tuple, direction: Tuple) -> Self {
+        Self {origin, direction}
+    }
+
+    pub fn position(&self, t: f64) -> Tuple {
+        self.origin + self.direction * t
+    }
+
+    pub fn transform(&self, m: &Matrix4) -> Self {
+        Self {
+            origin: *m * self.origin,
+            direction: *m * self.direction,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////
+/// Shapes
+///
+/// A unit sphere centred at the origin of its object space.
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Sphere {
+    pub material: Material,
+    transform: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Default for Sphere {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sphere {
+    pub fn new() -> Self {
+        Self {
+            material: Material::default(),
+            transform: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        }
+    }
+
+    pub fn transform(&self) -> Matrix4 {
+        self.transform
+    }
+
+    pub fn inverse_transform(&self) -> Matrix4 {
+        self.inverse
+    }
+
+    /// Panics if `m` is singular, since the ray could not be taken
+    /// into object space.
+    pub fn set_transform(&mut self, m: Matrix4) {
+        self.inverse = m.inverse()
+            .expect("Singular transform in Sphere::set_transform()");
+        self.transform = m;
+    }
+
+    pub fn with_transform(mut self, m: Matrix4) -> Self {
+        self.set_transform(m);
+        self
+    }
+
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+
+    /// Surface normal at a point given in world space. Normals are
+    /// taken back to world space with the inverse-transpose so they
+    /// stay perpendicular under non-uniform scaling.
+    pub fn normal_at(&self, world_point: Tuple) -> Tuple {
+        let object_point = self.inverse * world_point;
+        let object_normal = object_point - point(0.0, 0.0, 0.0);
+        let world_normal = self.inverse.transpose() * object_normal;
+        vector(world_normal.x, world_normal.y, world_normal.z).normalised()
+    }
+
+    pub fn intersect(&self, ray: &Ray) -> Intersections<'_> {
+        let ray = ray.transform(&self.inverse);
+        let sphere_to_ray = ray.origin - point(0.0, 0.0, 0.0);
+        let a = ray.direction.dot(ray.direction);
+        let b = 2.0 * ray.direction.dot(sphere_to_ray);
+        let c = sphere_to_ray.dot(sphere_to_ray) - 1.0;
+        let discriminant = b*b - 4.0*a*c;
+
+        if discriminant < 0.0 {
+            return Intersections::new(vec![]);
+        }
+        let sqrt_disc = f64::sqrt(discriminant);
+        Intersections::new(vec![
+            Intersection::new((-b - sqrt_disc) / (2.0*a), self),
+            Intersection::new((-b + sqrt_disc) / (2.0*a), self),
+        ])
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////
+/// Intersections
+///
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Intersection<'a> {
+    pub t: f64,
+    pub object: &'a Sphere,
+}
+
+impl<'a> Intersection<'a> {
+    pub fn new(t: f64, object: &'a Sphere) -> Self {
+        Self {t, object}
+    }
+
+    pub fn prepare_computations(&self, ray: &Ray) -> Computations<'a> {
+        let point = ray.position(self.t);
+        let eyev = -ray.direction;
+        let mut normalv = self.object.normal_at(point);
+        let inside = normalv.dot(eyev) < 0.0;
+        if inside {
+            normalv = -normalv;
+        }
+        Computations {
+            t: self.t,
+            object: self.object,
+            point, eyev, normalv, inside,
+        }
+    }
+}
+
+/// Values needed to shade a hit, precomputed once per intersection.
+#[derive(Debug,Clone,Copy)]
+pub struct Computations<'a> {
+    pub t: f64,
+    pub object: &'a Sphere,
+    pub point: Tuple,
+    pub eyev: Tuple,
+    pub normalv: Tuple,
+    /// True if the ray started inside the object, in which case the
+    /// normal has been flipped to face the eye.
+    pub inside: bool,
+}
+
+/// A collection of intersections, kept sorted by increasing `t`.
+#[derive(Debug,Clone,Default)]
+pub struct Intersections<'a> {
+    list: Vec<Intersection<'a>>,
+}
+
+impl<'a> Index<usize> for Intersections<'a> {
+    type Output = Intersection<'a>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.list[index]
+    }
+}
+
+impl<'a> Intersections<'a> {
+    pub fn new(mut list: Vec<Intersection<'a>>) -> Self {
+        list.sort_by(|a, b| a.t.total_cmp(&b.t));
+        Self {list}
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Intersection<'a>> {
+        self.list.iter()
+    }
+
+    pub fn extend(&mut self, other: Intersections<'a>) {
+        self.list.extend(other.list);
+        self.list.sort_by(|a, b| a.t.total_cmp(&b.t));
+    }
+
+    /// The visible intersection: the one with the lowest non-negative `t`.
+    pub fn hit(&self) -> Option<&Intersection<'a>> {
+        self.list.iter().find(|i| i.t >= 0.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::maths::almost_same;
+
+    #[test]
+    fn ray() {
+        let r = Ray::new(point(2.0, 3.0, 4.0), vector(1.0, 0.0, 0.0));
+        assert_eq!(r.position(0.0), point(2.0, 3.0, 4.0));
+        assert_eq!(r.position(1.0), point(3.0, 3.0, 4.0));
+        assert_eq!(r.position(-1.0), point(1.0, 3.0, 4.0));
+        assert_eq!(r.position(2.5), point(4.5, 3.0, 4.0));
+
+        let r = Ray::new(point(1.0, 2.0, 3.0), vector(0.0, 1.0, 0.0));
+        let r2 = r.transform(&Matrix4::translation(3.0, 4.0, 5.0));
+        assert_eq!(r2, Ray::new(point(4.0, 6.0, 8.0), vector(0.0, 1.0, 0.0)));
+        let r2 = r.transform(&Matrix4::scaling(2.0, 3.0, 4.0));
+        assert_eq!(r2, Ray::new(point(2.0, 6.0, 12.0), vector(0.0, 3.0, 0.0)));
+    }
+
+    #[test]
+    fn sphere_intersect() {
+        let s = Sphere::new();
+        let xs = s.intersect(&Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0)));
+        assert_eq!(xs.len(), 2);
+        assert!(almost_same(xs[0].t, 4.0));
+        assert!(almost_same(xs[1].t, 6.0));
+        assert_eq!(xs[0].object, &s);
+
+        let xs = s.intersect(&Ray::new(point(0.0, 1.0, -5.0), vector(0.0, 0.0, 1.0)));
+        assert_eq!(xs.len(), 2);
+        assert!(almost_same(xs[0].t, 5.0));
+        assert!(almost_same(xs[1].t, 5.0));
+
+        let xs = s.intersect(&Ray::new(point(0.0, 2.0, -5.0), vector(0.0, 0.0, 1.0)));
+        assert!(xs.is_empty());
+
+        let xs = s.intersect(&Ray::new(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0)));
+        assert!(almost_same(xs[0].t, -1.0));
+        assert!(almost_same(xs[1].t, 1.0));
+
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let s = Sphere::new().with_transform(Matrix4::scaling(2.0, 2.0, 2.0));
+        let xs = s.intersect(&r);
+        assert!(almost_same(xs[0].t, 3.0));
+        assert!(almost_same(xs[1].t, 7.0));
+
+        let s = Sphere::new().with_transform(Matrix4::translation(5.0, 0.0, 0.0));
+        assert!(s.intersect(&r).is_empty());
+    }
+
+    #[test]
+    fn sphere_normal() {
+        let s = Sphere::new();
+        assert_eq!(s.normal_at(point(1.0, 0.0, 0.0)), vector(1.0, 0.0, 0.0));
+        assert_eq!(s.normal_at(point(0.0, 0.0, 1.0)), vector(0.0, 0.0, 1.0));
+        let k = f64::sqrt(3.0)/3.0;
+        let n = s.normal_at(point(k, k, k));
+        assert_eq!(n, vector(k, k, k));
+        assert_eq!(n, n.normalised());
+
+        let s = Sphere::new().with_transform(Matrix4::translation(0.0, 1.0, 0.0));
+        let h = f64::sqrt(2.0)/2.0;
+        let n = s.normal_at(point(0.0, 1.0 + h, -h));
+        assert_eq!(n, vector(0.0, h, -h));
+
+        let m = Matrix4::identity()
+            .rotate_z(std::f64::consts::PI/5.0)
+            .scale(1.0, 0.5, 1.0);
+        let s = Sphere::new().with_transform(m);
+        let n = s.normal_at(point(0.0, h, -h));
+        assert!(almost_same(n.x, 0.0));
+        assert!(f64::abs(n.y - 0.97014) < 1.0e-5);
+        assert!(f64::abs(n.z + 0.24254) < 1.0e-5);
+        assert!(n.is_vector());
+    }
+
+    #[test]
+    fn computations() {
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let s = Sphere::new();
+        let comps = Intersection::new(4.0, &s).prepare_computations(&r);
+        assert!(almost_same(comps.t, 4.0));
+        assert_eq!(comps.point, point(0.0, 0.0, -1.0));
+        assert_eq!(comps.eyev, vector(0.0, 0.0, -1.0));
+        assert_eq!(comps.normalv, vector(0.0, 0.0, -1.0));
+        assert!(!comps.inside);
+
+        let r = Ray::new(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0));
+        let comps = Intersection::new(1.0, &s).prepare_computations(&r);
+        assert_eq!(comps.point, point(0.0, 0.0, 1.0));
+        assert_eq!(comps.eyev, vector(0.0, 0.0, -1.0));
+        assert_eq!(comps.normalv, vector(0.0, 0.0, -1.0));
+        assert!(comps.inside);
+    }
+
+    #[test]
+    fn hit() {
+        let s = Sphere::new();
+        let xs = Intersections::new(vec![Intersection::new(1.0, &s), Intersection::new(2.0, &s)]);
+        assert!(almost_same(xs.hit().unwrap().t, 1.0));
+
+        let xs = Intersections::new(vec![Intersection::new(-1.0, &s), Intersection::new(1.0, &s)]);
+        assert!(almost_same(xs.hit().unwrap().t, 1.0));
+
+        let xs = Intersections::new(vec![Intersection::new(-2.0, &s), Intersection::new(-1.0, &s)]);
+        assert!(xs.hit().is_none());
+
+        let xs = Intersections::new(vec![
+            Intersection::new(5.0, &s),
+            Intersection::new(7.0, &s),
+            Intersection::new(-3.0, &s),
+            Intersection::new(2.0, &s),
+        ]);
+        assert!(almost_same(xs.hit().unwrap().t, 2.0));
+    }
+}
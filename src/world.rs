@@ -0,0 +1,118 @@
+use crate::canvas::{Colour,Rgba};
+use crate::ray::{Ray,Sphere,Intersections,Computations};
+use crate::lighting::{lighting,PointLight};
+
+#[derive(Debug,Clone,Default)]
+pub struct World {
+    pub objects: Vec<Sphere>,
+    pub lights: Vec<PointLight>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intersect_world(&self, ray: &Ray) -> Intersections<'_> {
+        let mut xs = Intersections::default();
+        for object in &self.objects {
+            xs.extend(object.intersect(ray));
+        }
+        xs
+    }
+
+    /// Colour at a hit, summed over every light in the world.
+    pub fn shade_hit(&self, comps: &Computations) -> Colour {
+        self.lights.iter().fold(Colour::new(0.0, 0.0, 0.0), |acc, light| {
+            acc + lighting(&comps.object.material, light,
+                comps.point, comps.eyev, comps.normalv)
+        })
+    }
+
+    /// Colour seen along `ray`; black if it hits nothing.
+    pub fn colour_at(&self, ray: &Ray) -> Colour {
+        self.rgba_at(ray).colour()
+    }
+
+    /// As `colour_at`, but rays that miss everything are transparent.
+    pub fn rgba_at(&self, ray: &Ray) -> Rgba {
+        let xs = self.intersect_world(ray);
+        match xs.hit() {
+            Some(hit) => Rgba::from_colour(self.shade_hit(&hit.prepare_computations(ray)), 1.0),
+            None => Rgba::transparent(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::maths::{point,vector,almost_same,Matrix4};
+    use crate::lighting::Material;
+    use crate::ray::Intersection;
+
+    /// Two concentric spheres lit from the upper left, used by the
+    /// world and camera tests.
+    pub(crate) fn default_world() -> World {
+        let light = PointLight::new(point(-10.0, 10.0, -10.0), Colour::new(1.0, 1.0, 1.0));
+        let s1 = Sphere::new().with_material(Material {
+            colour: Colour::new(0.8, 1.0, 0.6),
+            diffuse: 0.7,
+            specular: 0.2,
+            ..Material::default()
+        });
+        let s2 = Sphere::new().with_transform(Matrix4::scaling(0.5, 0.5, 0.5));
+        World {
+            objects: vec![s1, s2],
+            lights: vec![light],
+        }
+    }
+
+    #[test]
+    fn intersect_world() {
+        let w = default_world();
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let xs = w.intersect_world(&r);
+        assert_eq!(xs.len(), 4);
+        assert!(almost_same(xs[0].t, 4.0));
+        assert!(almost_same(xs[1].t, 4.5));
+        assert!(almost_same(xs[2].t, 5.5));
+        assert!(almost_same(xs[3].t, 6.0));
+    }
+
+    fn assert_close(c: Colour, expected: Colour) {
+        assert!(f64::abs(c.red - expected.red) < 1.0e-5, "{:?} != {:?}", c, expected);
+        assert!(f64::abs(c.green - expected.green) < 1.0e-5, "{:?} != {:?}", c, expected);
+        assert!(f64::abs(c.blue - expected.blue) < 1.0e-5, "{:?} != {:?}", c, expected);
+    }
+
+    #[test]
+    fn shade_hit() {
+        let w = default_world();
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let comps = Intersection::new(4.0, &w.objects[0]).prepare_computations(&r);
+        assert_close(w.shade_hit(&comps), Colour::new(0.38066, 0.47583, 0.2855));
+
+        let mut w = default_world();
+        w.lights = vec![PointLight::new(point(0.0, 0.25, 0.0), Colour::new(1.0, 1.0, 1.0))];
+        let r = Ray::new(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0));
+        let comps = Intersection::new(0.5, &w.objects[1]).prepare_computations(&r);
+        assert_close(w.shade_hit(&comps), Colour::new(0.90498, 0.90498, 0.90498));
+    }
+
+    #[test]
+    fn colour_at() {
+        let w = default_world();
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 1.0, 0.0));
+        assert_eq!(w.colour_at(&r), Colour::new(0.0, 0.0, 0.0));
+
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert_close(w.colour_at(&r), Colour::new(0.38066, 0.47583, 0.2855));
+
+        let mut w = default_world();
+        w.objects[0].material.ambient = 1.0;
+        w.objects[1].material.ambient = 1.0;
+        let r = Ray::new(point(0.0, 0.0, 0.75), vector(0.0, 0.0, -1.0));
+        assert_eq!(w.colour_at(&r), w.objects[1].material.colour);
+    }
+}